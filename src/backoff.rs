@@ -0,0 +1,52 @@
+use rand::Rng;
+use tokio::time::Duration;
+
+// Exponential backoff with jitter used by feed supervisors when reconnecting after a
+// WebSocket disconnect or error. Parameters are plain fields so individual feeds can be
+// tuned (e.g. a feed hitting rate limits might want a higher initial delay).
+#[derive(Debug, Clone)]
+pub struct BackoffConfig {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+        }
+    }
+}
+
+// Tracks the current delay for a single reconnecting connection. Create one per
+// supervisor loop and call `reset` once the connection is healthy again.
+pub struct Backoff {
+    config: BackoffConfig,
+    current_delay: Duration,
+}
+
+impl Backoff {
+    pub fn new(config: BackoffConfig) -> Self {
+        let current_delay = config.initial_delay;
+        Self { config, current_delay }
+    }
+
+    // Returns the delay to wait before the next reconnect attempt (with +/-20% jitter so
+    // many reconnecting clients don't all retry in lockstep), then grows the base delay
+    // towards `max_delay` for next time.
+    pub fn next_delay(&mut self) -> Duration {
+        let jitter = rand::thread_rng().gen_range(0.8..1.2);
+        let delay = self.current_delay.mul_f64(jitter);
+
+        self.current_delay = self.current_delay.mul_f64(self.config.multiplier).min(self.config.max_delay);
+
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current_delay = self.config.initial_delay;
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use colored::Colorize;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::backoff::{Backoff, BackoffConfig};
+use crate::debug_print;
+use crate::depth_feed::handle_update;
+use crate::event_buffer::EventBuffer;
+use crate::events::{self, MarketEvent};
+use crate::messages::{
+    BestDeal, CombinedStreamEnvelope, ControlMethod, StreamControlRequest, WebsocketEvent, handle_ping_message,
+};
+use crate::shutdown::ShutdownReceiver;
+
+// Combined-stream endpoint: one socket can carry many symbols and stream types at once.
+// Streams are (un)subscribed entirely through SUBSCRIBE/UNSUBSCRIBE control frames, so we
+// always connect to the bare endpoint and subscribe right after the handshake.
+const COMBINED_STREAM_URL: &str = "wss://stream.binance.com:9443/stream";
+
+type CombinedWrite = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+type CombinedRead = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
+
+// A runtime (un)subscribe request for a running combined-stream connection
+enum SubscriptionCommand {
+    Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
+}
+
+// Handle used by callers to reconfigure subscriptions without reconnecting
+#[derive(Clone)]
+pub struct CombinedStreamHandle {
+    commands: mpsc::UnboundedSender<SubscriptionCommand>,
+}
+
+impl CombinedStreamHandle {
+    // Public API for callers that want to reconfigure a running combined-stream connection;
+    // nothing in this binary exercises it yet (see the commented example in `main.rs`), so
+    // allow it to go unused rather than delete behavior a caller may start using next.
+    #[allow(dead_code)]
+    pub fn subscribe(&self, streams: Vec<String>) {
+        if self.commands.send(SubscriptionCommand::Subscribe(streams)).is_err() {
+            eprintln!("{}", "Combined stream: subscribe request dropped, connection is closed".red().bold());
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn unsubscribe(&self, streams: Vec<String>) {
+        if self.commands.send(SubscriptionCommand::Unsubscribe(streams)).is_err() {
+            eprintln!("{}", "Combined stream: unsubscribe request dropped, connection is closed".red().bold());
+        }
+    }
+}
+
+// Opens a single combined-stream connection subscribed to `initial_streams` and returns a
+// handle that lets the caller add or drop subscriptions at runtime. Depth updates are routed
+// into the existing event buffer / order book pipeline; aggTrade, depth5 (best deal) and the
+// other stream types are published as `MarketEvent`s on `event_sender`. The connection
+// reconnects with exponential backoff and resubscribes to everything currently active
+// whenever it drops.
+pub async fn start_combined_feed(
+    initial_streams: Vec<String>,
+    event_buffer: Arc<EventBuffer>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    latest_best_deals: Arc<RwLock<HashMap<String, BestDeal>>>,
+    shutdown: ShutdownReceiver,
+) -> CombinedStreamHandle {
+    start_combined_feed_with_backoff(
+        initial_streams, event_buffer, event_sender, latest_best_deals,
+        shutdown, BackoffConfig::default(),
+    ).await
+}
+
+pub async fn start_combined_feed_with_backoff(
+    initial_streams: Vec<String>,
+    event_buffer: Arc<EventBuffer>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    latest_best_deals: Arc<RwLock<HashMap<String, BestDeal>>>,
+    shutdown: ShutdownReceiver,
+    backoff_config: BackoffConfig,
+) -> CombinedStreamHandle {
+    let (command_tx, command_rx) = mpsc::unbounded_channel();
+    let active_streams = Arc::new(RwLock::new(initial_streams.into_iter().collect::<HashSet<_>>()));
+
+    tokio::spawn(run_combined_feed(
+        command_rx, active_streams, event_buffer, event_sender,
+        latest_best_deals, shutdown, backoff_config,
+    ));
+
+    CombinedStreamHandle { commands: command_tx }
+}
+
+// Supervises the combined-stream connection for its whole lifetime: connects, subscribes to
+// everything in `active_streams`, serves the session until it drops, then reconnects with
+// backoff and resubscribes. Exits once every `CombinedStreamHandle` has been dropped or
+// shutdown is requested (sending a Close frame first in the latter case).
+async fn run_combined_feed(
+    mut command_rx: mpsc::UnboundedReceiver<SubscriptionCommand>,
+    active_streams: Arc<RwLock<HashSet<String>>>,
+    event_buffer: Arc<EventBuffer>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    latest_best_deals: Arc<RwLock<HashMap<String, BestDeal>>>,
+    mut shutdown: ShutdownReceiver,
+    backoff_config: BackoffConfig,
+) {
+    let mut backoff = Backoff::new(backoff_config);
+
+    loop {
+        println!("Starting combined stream connection...");
+
+        match tokio_tungstenite::connect_async(COMBINED_STREAM_URL).await {
+            Ok((ws_stream, _)) => {
+                backoff.reset();
+                let (mut write, mut read) = ws_stream.split();
+                let next_request_id = AtomicU64::new(1);
+
+                let streams: Vec<String> = active_streams.read().await.iter().cloned().collect();
+                if !streams.is_empty() {
+                    send_control_frame(&mut write, ControlMethod::Subscribe, streams, &next_request_id).await;
+                }
+
+                let outcome = run_combined_session(
+                    &mut write,
+                    &mut read,
+                    &mut command_rx,
+                    &active_streams,
+                    &next_request_id,
+                    Arc::clone(&event_buffer),
+                    event_sender.clone(),
+                    Arc::clone(&latest_best_deals),
+                    &mut shutdown,
+                ).await;
+
+                match outcome {
+                    SessionOutcome::AllHandlesDropped => {
+                        println!("Combined stream: all handles dropped, shutting down.");
+                        return;
+                    }
+                    SessionOutcome::ShutdownRequested => {
+                        debug_print!("Combined stream: shutdown requested, closing connection.");
+                        let _ = write.send(Message::Close(None)).await;
+                        return;
+                    }
+                    SessionOutcome::Reconnect => {}
+                }
+            }
+            Err(err) => {
+                eprintln!("{}", format!("Combined stream: failed to connect: {:?}", err).red().bold());
+            }
+        }
+
+        let delay = backoff.next_delay();
+        debug_print!("Combined stream: reconnecting in {:?}", delay);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// Why the current connection attempt ended, and what the supervisor in `run_combined_feed`
+// should do about it.
+enum SessionOutcome {
+    Reconnect,
+    AllHandlesDropped,
+    ShutdownRequested,
+}
+
+// Serves one connection until it errors, closes, every `CombinedStreamHandle` is dropped, or
+// shutdown is requested.
+#[allow(clippy::too_many_arguments)]
+async fn run_combined_session(
+    write: &mut CombinedWrite,
+    read: &mut CombinedRead,
+    command_rx: &mut mpsc::UnboundedReceiver<SubscriptionCommand>,
+    active_streams: &Arc<RwLock<HashSet<String>>>,
+    next_request_id: &AtomicU64,
+    event_buffer: Arc<EventBuffer>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    latest_best_deals: Arc<RwLock<HashMap<String, BestDeal>>>,
+    shutdown: &mut ShutdownReceiver,
+) -> SessionOutcome {
+    loop {
+        tokio::select! {
+            _ = shutdown.recv_shutdown() => {
+                return SessionOutcome::ShutdownRequested;
+            }
+            command = command_rx.recv() => {
+                match command {
+                    Some(SubscriptionCommand::Subscribe(streams)) => {
+                        active_streams.write().await.extend(streams.iter().cloned());
+                        send_control_frame(write, ControlMethod::Subscribe, streams, next_request_id).await;
+                    }
+                    Some(SubscriptionCommand::Unsubscribe(streams)) => {
+                        {
+                            let mut active = active_streams.write().await;
+                            for stream in &streams {
+                                active.remove(stream);
+                            }
+                        }
+                        send_control_frame(write, ControlMethod::Unsubscribe, streams, next_request_id).await;
+                    }
+                    None => return SessionOutcome::AllHandlesDropped, // every handle dropped, nothing left to reconfigure
+                }
+            }
+            msg = read.next() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        route_combined_message(
+                            &text,
+                            Arc::clone(&event_buffer),
+                            event_sender.clone(),
+                            Arc::clone(&latest_best_deals),
+                        ).await;
+                    }
+                    Some(Ok(Message::Ping(data))) => {
+                        handle_ping_message("Combined stream", data, write).await;
+                    }
+                    Some(Err(err)) => {
+                        eprintln!("{}", format!("Combined stream: Error reading WebSocket: {:?}", err).red().bold());
+                        return SessionOutcome::Reconnect;
+                    }
+                    Some(_) => {}
+                    None => {
+                        println!("Combined stream: WebSocket connection closed.");
+                        return SessionOutcome::Reconnect;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn send_control_frame(
+    write: &mut CombinedWrite,
+    method: ControlMethod,
+    params: Vec<String>,
+    next_request_id: &AtomicU64,
+) {
+    let request = StreamControlRequest {
+        method,
+        params,
+        id: next_request_id.fetch_add(1, Ordering::Relaxed),
+    };
+
+    match serde_json::to_string(&request) {
+        Ok(frame) => {
+            debug_print!("Combined stream: sending control frame {}", frame);
+            if let Err(err) = write.send(Message::Text(frame)).await {
+                eprintln!("{}", format!("Combined stream: failed to send control frame: {:?}", err).red().bold());
+            }
+        }
+        Err(err) => eprintln!("{}", format!("Combined stream: failed to serialize control frame: {:?}", err).red().bold()),
+    }
+}
+
+async fn route_combined_message(
+    text: &str,
+    event_buffer: Arc<EventBuffer>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    latest_best_deals: Arc<RwLock<HashMap<String, BestDeal>>>,
+) {
+    let envelope: CombinedStreamEnvelope = match serde_json::from_str(text) {
+        Ok(envelope) => envelope,
+        Err(_) => {
+            // control frame acks ({"result":null,"id":1}) don't match the envelope, ignore them
+            debug_print!("Combined stream: received non-envelope frame: {}", text);
+            return;
+        }
+    };
+
+    // stream names look like "<symbol>@<suffix>"
+    let symbol = envelope.stream.split('@').next().unwrap_or(&envelope.stream).to_string();
+
+    match serde_json::from_value::<WebsocketEvent>(envelope.data) {
+        Ok(WebsocketEvent::OrderBookUpdate(update)) => {
+            handle_update(event_buffer, update, event_sender).await;
+        }
+        Ok(WebsocketEvent::AggTrade(trade)) => events::publish(&event_sender, MarketEvent::AggTrade(trade)),
+        Ok(WebsocketEvent::BestDeal(best_deal)) => {
+            // stashed per symbol for that symbol's book validator to cross-check against its
+            // own built order book
+            latest_best_deals.write().await.insert(symbol.clone(), best_deal.clone());
+            events::publish(&event_sender, MarketEvent::BestDeal { symbol, best_deal });
+        }
+        Ok(WebsocketEvent::Kline(kline)) => events::publish(&event_sender, MarketEvent::Kline(kline)),
+        Ok(WebsocketEvent::Ticker24hr(ticker)) => events::publish(&event_sender, MarketEvent::Ticker24hr(ticker)),
+        Ok(WebsocketEvent::BookTicker(book_ticker)) => events::publish(&event_sender, MarketEvent::BookTicker(book_ticker)),
+        Ok(WebsocketEvent::MarkPrice(mark_price)) => events::publish(&event_sender, MarketEvent::MarkPrice(mark_price)),
+        Err(err) => eprintln!("{}", format!("Combined stream: failed to parse payload on {}: {:?}", envelope.stream, err).red().bold()),
+    }
+}
@@ -1,69 +1,275 @@
+use std::pin::Pin;
 use std::sync::Arc;
 use colored::Colorize;
-use futures_util::StreamExt;
-use tokio::sync::RwLock;
-use tokio_tungstenite::tungstenite::Message;
+use futures_util::stream::{FuturesUnordered, SelectAll, SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
 use crate::{debug_print};
+use crate::backoff::{Backoff, BackoffConfig};
 use crate::event_buffer::EventBuffer;
+use crate::events::MarketEvent;
 use crate::messages::{OrderBookUpdate, handle_ping_message};
-use crate::order_book::{InstrumentState, OrderBook};
-use crate::recovery::TimeoutState;
+use crate::order_book::StateLock;
+use crate::recovery::{recover_order_book, TimeoutState};
+use crate::shutdown::ShutdownReceiver;
+
+type DepthWrite = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+type DepthRead = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
 
-async fn handle_update(
-    event_buffer: Arc<RwLock<EventBuffer>>,
+// Boxed so `reconnect_connection` (and every other push site) can push a freshly-built
+// `connect_after_delay` future into the same `FuturesUnordered` - an `impl Future` parameter
+// would fix the pushed future's type to whatever the *caller* first instantiated it with,
+// which a function pushing its own new future can never satisfy.
+type PendingConnect = Pin<Box<dyn std::future::Future<Output = (usize, Result<(DepthWrite, DepthRead), String>)> + Send>>;
+
+// Hands `update` to the shared dispatcher, which routes it to its own symbol's buffer/state -
+// a separate event buffer and lock set per instrument is no longer needed.
+pub(crate) async fn handle_update(
+    event_buffer: Arc<EventBuffer>,
     update: OrderBookUpdate,
-    order_book: Arc<RwLock<OrderBook>>,
-    state: Arc<RwLock<InstrumentState>>,
-    timeout_state: Arc<TimeoutState>,
+    event_sender: broadcast::Sender<MarketEvent>,
 ) {
-    let mut buffer = event_buffer.write().await;
-    buffer
-        .buffer_and_process_update(update, Arc::clone(&order_book), Arc::clone(&state), Arc::clone(&timeout_state))
-        .await;
+    event_buffer.buffer_and_process_update(update, event_sender).await;
 }
 
-pub async fn start_depth_feed(
-    ws_url: String,
-    event_buffer: Arc<RwLock<EventBuffer>>,
-    order_book: Arc<RwLock<OrderBook>>,
-    state: Arc<RwLock<InstrumentState>>,
-    connection_id: usize,
-    timeout_state: Arc<TimeoutState>,
-) {
+// Whether `update` is provably already reflected in the book, and can be dropped outright
+// instead of handed to the event buffer. Must only ever compare against what's actually been
+// *applied* (`state_lock.last_applied_id()`) - with N arbitration connections polled through a
+// single `SelectAll` there's no ordering guarantee between them, so a watermark raised by
+// whichever connection happens to get dispatched first would drop a real, not-yet-applied
+// range from another connection and manufacture a gap the event buffer's own reorder/gap
+// handling never gets a chance to see.
+fn already_applied(update: &OrderBookUpdate, state_lock: &StateLock) -> bool {
+    update.last_trade_id <= state_lock.last_applied_id()
+}
+
+// What a tagged connection stream yields: either a WebSocket item, or an explicit
+// end-of-stream marker so the pool loop knows exactly which connection needs reconnecting
+// (SelectAll otherwise just silently drops a sub-stream once it's exhausted).
+enum ConnectionMessage {
+    Item(Result<Message, WsError>),
+    Closed,
+}
+
+fn connection_stream(connection_id: usize, read: DepthRead) -> impl futures_util::Stream<Item = (usize, ConnectionMessage)> {
+    futures_util::stream::unfold((read, false), move |(mut read, ended)| async move {
+        if ended {
+            return None;
+        }
+        match read.next().await {
+            Some(msg) => Some(((connection_id, ConnectionMessage::Item(msg)), (read, false))),
+            None => Some(((connection_id, ConnectionMessage::Closed), (read, true))),
+        }
+    })
+}
+
+async fn connect_after_delay(connection_id: usize, ws_url: String, delay: Duration) -> (usize, Result<(DepthWrite, DepthRead), String>) {
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+
     println!("Connection {}: Starting WebSocket connection...", connection_id);
 
-    let (ws_stream, _) = tokio_tungstenite::connect_async(&ws_url)
-        .await
-        .expect("Failed to connect to WebSocket");
+    match tokio_tungstenite::connect_async(&ws_url).await {
+        Ok((ws_stream, _)) => (connection_id, Ok(ws_stream.split())),
+        Err(err) => (connection_id, Err(format!("Failed to connect to WebSocket: {:?}", err))),
+    }
+}
 
-    let (mut write, mut read) = ws_stream.split();
+// Runs all `num_connections` arbitration connections for the same depth stream in a single
+// task: every read half is tagged with its connection id and polled together through one
+// `SelectAll`, instead of one task and lock set per connection. This also gives a single
+// place to dedupe overlapping first_trade_id/last_trade_id ranges across connections before
+// any of them touch the book.
+#[allow(clippy::too_many_arguments)]
+pub async fn start_depth_feed_pool(
+    symbol: String,
+    ws_url: String,
+    num_connections: usize,
+    event_buffer: Arc<EventBuffer>,
+    state_lock: Arc<StateLock>,
+    snapshot_url: String,
+    timeout_state: Arc<TimeoutState>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    shutdown: ShutdownReceiver,
+) {
+    start_depth_feed_pool_with_backoff(
+        symbol, ws_url, num_connections, event_buffer, state_lock, snapshot_url, timeout_state,
+        event_sender, shutdown, BackoffConfig::default(),
+    ).await;
+}
 
-    let connection_name = format!("Depth connection {}", connection_id);
+#[allow(clippy::too_many_arguments)]
+pub async fn start_depth_feed_pool_with_backoff(
+    symbol: String,
+    ws_url: String,
+    num_connections: usize,
+    event_buffer: Arc<EventBuffer>,
+    state_lock: Arc<StateLock>,
+    snapshot_url: String,
+    timeout_state: Arc<TimeoutState>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    mut shutdown: ShutdownReceiver,
+    backoff_config: BackoffConfig,
+) {
+    let mut writes: Vec<Option<DepthWrite>> = (0..num_connections).map(|_| None).collect();
+    let mut backoffs: Vec<Backoff> = (0..num_connections).map(|_| Backoff::new(backoff_config.clone())).collect();
 
-    while let Some(msg) = read.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                if let Ok(update) = serde_json::from_str::<OrderBookUpdate>(&text) {
-                    debug_print!(
-                        "Connection {}: Received update first_trade_id = {}, last_trade_id = {}",
-                        connection_id, update.first_trade_id, update.last_trade_id
-                    );
+    let mut connections = SelectAll::new();
+    let mut pending_connects: FuturesUnordered<PendingConnect> = FuturesUnordered::new();
+    for connection_id in 0..num_connections {
+        pending_connects.push(Box::pin(connect_after_delay(connection_id, ws_url.clone(), Duration::ZERO)));
+    }
 
-                    handle_update(Arc::clone(&event_buffer), update, Arc::clone(&order_book), Arc::clone(&state), Arc::clone(&timeout_state)).await;
-                }
-            }
-            Err(err) => {
-                eprintln!("{}", format!("Connection {}: Error reading WebSocket: {:?}", connection_id, err).red().bold());
-                break;
+    loop {
+        tokio::select! {
+            _ = shutdown.recv_shutdown() => {
+                debug_print!("Depth feed pool: shutdown requested, closing all connections.");
+                close_all_connections(&mut writes).await;
+                return;
             }
-            Ok(Message::Ping(data)) => {
-                handle_ping_message(&connection_name, data, &mut write).await;
+            Some((connection_id, result)) = pending_connects.next(), if !pending_connects.is_empty() => {
+                match result {
+                    Ok((write, read)) => {
+                        debug_print!("Connection {}: connected", connection_id);
+                        writes[connection_id] = Some(write);
+                        backoffs[connection_id].reset();
+                        connections.push(Box::pin(connection_stream(connection_id, read)));
+                    }
+                    Err(err) => {
+                        eprintln!("{}", format!("Connection {}: {}", connection_id, err).red().bold());
+                        let delay = backoffs[connection_id].next_delay();
+                        pending_connects.push(Box::pin(connect_after_delay(connection_id, ws_url.clone(), delay)));
+                    }
+                }
             }
-            _ => {
-                eprintln!("{}", format!("Connection {}: Unknown message received: {:?}", connection_id, msg).red().bold());
+            Some((connection_id, conn_msg)) = connections.next(), if !connections.is_empty() => {
+                match conn_msg {
+                    ConnectionMessage::Item(Ok(Message::Text(text))) => {
+                        if let Ok(update) = serde_json::from_str::<OrderBookUpdate>(&text) {
+                            if already_applied(&update, &state_lock) {
+                                debug_print!(
+                                    "Connection {}: dropping already-applied update first_trade_id = {}, last_trade_id = {}",
+                                    connection_id, update.first_trade_id, update.last_trade_id
+                                );
+                            } else {
+                                debug_print!(
+                                    "Connection {}: Received update first_trade_id = {}, last_trade_id = {}",
+                                    connection_id, update.first_trade_id, update.last_trade_id
+                                );
+                                handle_update(Arc::clone(&event_buffer), update, event_sender.clone()).await;
+                            }
+                        }
+                    }
+                    ConnectionMessage::Item(Ok(Message::Ping(data))) => {
+                        if let Some(write) = writes[connection_id].as_mut() {
+                            handle_ping_message(&format!("Depth connection {}", connection_id), data, write).await;
+                        }
+                    }
+                    ConnectionMessage::Item(Ok(_)) => {
+                        eprintln!("{}", format!("Connection {}: Unknown message received", connection_id).red().bold());
+                    }
+                    ConnectionMessage::Item(Err(err)) => {
+                        eprintln!("{}", format!("Connection {}: Error reading WebSocket: {:?}", connection_id, err).red().bold());
+                        reconnect_connection(
+                            connection_id, &ws_url, &symbol, &state_lock, &event_buffer, &snapshot_url, &timeout_state,
+                            &event_sender, &mut writes, &mut backoffs, &mut pending_connects,
+                        ).await;
+                    }
+                    ConnectionMessage::Closed => {
+                        println!("Connection {}: WebSocket connection closed.", connection_id);
+                        reconnect_connection(
+                            connection_id, &ws_url, &symbol, &state_lock, &event_buffer, &snapshot_url, &timeout_state,
+                            &event_sender, &mut writes, &mut backoffs, &mut pending_connects,
+                        ).await;
+                    }
+                }
             }
         }
     }
+}
+
+// Sends a proper Close frame down every still-open connection instead of just dropping the
+// sockets, so the server sees a clean close rather than an abrupt TCP reset.
+async fn close_all_connections(writes: &mut [Option<DepthWrite>]) {
+    for write in writes.iter_mut().flatten() {
+        if let Err(err) = write.send(Message::Close(None)).await {
+            eprintln!("{}", format!("Depth feed pool: failed to send Close frame: {:?}", err).red().bold());
+        }
+    }
+}
+
+// A connection dying means this connection's view of the sequence is gone; force a fresh
+// snapshot and re-sync immediately, rather than assuming the other N-1 connections cover the
+// gap or relying solely on the unrelated inactivity timeout in `monitor_and_recover`.
+#[allow(clippy::too_many_arguments)]
+async fn reconnect_connection(
+    connection_id: usize,
+    ws_url: &str,
+    symbol: &str,
+    state_lock: &Arc<StateLock>,
+    event_buffer: &Arc<EventBuffer>,
+    snapshot_url: &str,
+    timeout_state: &Arc<TimeoutState>,
+    event_sender: &broadcast::Sender<MarketEvent>,
+    writes: &mut [Option<DepthWrite>],
+    backoffs: &mut [Backoff],
+    pending_connects: &mut FuturesUnordered<PendingConnect>,
+) {
+    writes[connection_id] = None;
+
+    recover_order_book(
+        symbol.to_string(), snapshot_url.to_string(), Arc::clone(state_lock),
+        Arc::clone(event_buffer), Arc::clone(timeout_state), event_sender.clone(),
+    ).await;
+
+    let delay = backoffs[connection_id].next_delay();
+    debug_print!("Connection {}: reconnecting in {:?}", connection_id, delay);
+    pending_connects.push(Box::pin(connect_after_delay(connection_id, ws_url.to_string(), delay)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::Snapshot;
+    use crate::order_book::SymbolFilters;
+
+    fn state_lock_applied_through(last_applied_id: u64) -> StateLock {
+        let state_lock = StateLock::new(SymbolFilters::new("0.01", "0.00001"));
+        state_lock.apply_snapshot(&Snapshot { last_update_id: last_applied_id, bids: vec![], asks: vec![] });
+        state_lock
+    }
+
+    fn update_with_last_trade_id(last_trade_id: u64) -> OrderBookUpdate {
+        OrderBookUpdate {
+            event_type: "depthUpdate".to_string(),
+            event_time: 0,
+            symbol: "btcusdt".to_string(),
+            first_trade_id: last_trade_id,
+            last_trade_id,
+            bids: vec![],
+            asks: vec![],
+        }
+    }
 
-    println!("Connection {}: WebSocket connection closed.", connection_id);
+    #[test]
+    fn drops_updates_already_applied_to_the_book() {
+        let state_lock = state_lock_applied_through(10);
+        assert!(already_applied(&update_with_last_trade_id(10), &state_lock));
+        assert!(already_applied(&update_with_last_trade_id(5), &state_lock));
+    }
+
+    #[test]
+    fn keeps_updates_not_yet_applied_regardless_of_dispatch_order() {
+        let state_lock = state_lock_applied_through(10);
+        // a range from a connection that raced ahead of another, already-dispatched one must
+        // still be kept - only state_lock.last_applied_id() decides, not dispatch order
+        assert!(!already_applied(&update_with_last_trade_id(11), &state_lock));
+        assert!(!already_applied(&update_with_last_trade_id(100), &state_lock));
+    }
 }
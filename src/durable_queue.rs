@@ -0,0 +1,90 @@
+use colored::Colorize;
+use sled::Tree;
+
+use crate::debug_print;
+use crate::messages::OrderBookUpdate;
+
+// Persistent backing store for `EventBuffer`'s pending queue. Updates are written here before
+// they're acknowledged into the in-memory heap, and removed once `OrderBook::apply_update`
+// has consumed them, so a crash mid-recovery loses nothing - on restart the queue reloads
+// straight from disk instead of starting empty.
+//
+// Keys are `<symbol>\0<global_update_id as big-endian u64>`, so a prefix scan over one
+// symbol naturally yields its updates in sequence order without needing a separate index.
+pub struct DurableQueue {
+    pending: Tree,
+}
+
+fn encode_key(symbol: &str, global_update_id: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(symbol.len() + 1 + 8);
+    key.extend_from_slice(symbol.as_bytes());
+    key.push(0);
+    key.extend_from_slice(&global_update_id.to_be_bytes());
+    key
+}
+
+fn key_prefix(symbol: &str) -> Vec<u8> {
+    let mut prefix = symbol.as_bytes().to_vec();
+    prefix.push(0);
+    prefix
+}
+
+impl DurableQueue {
+    pub fn open(db_path: &str) -> sled::Result<Self> {
+        let db = sled::open(db_path)?;
+        let pending = db.open_tree("pending_updates")?;
+        Ok(Self { pending })
+    }
+
+    // Appends `update` under `(symbol, last_trade_id)`, overwriting any previous entry with
+    // the same id (e.g. a retried push after a crash between the durable write and the
+    // in-memory one).
+    pub fn push(&self, symbol: &str, update: &OrderBookUpdate) -> sled::Result<()> {
+        let key = encode_key(symbol, update.last_trade_id);
+        let value = serde_json::to_vec(update).expect("OrderBookUpdate is always serializable");
+        self.pending.insert(key, value)?;
+        Ok(())
+    }
+
+    // Removes the entry for `global_update_id` once it has been applied (or discarded as
+    // outdated) so the durable queue never grows past what's actually still pending.
+    pub fn remove(&self, symbol: &str, global_update_id: u64) -> sled::Result<()> {
+        self.pending.remove(encode_key(symbol, global_update_id))?;
+        Ok(())
+    }
+
+    // All updates still pending for `symbol`, in ascending `last_trade_id` order, for
+    // reloading into the in-memory heap on startup.
+    pub fn load_pending(&self, symbol: &str) -> sled::Result<Vec<OrderBookUpdate>> {
+        let mut updates = Vec::new();
+        for entry in self.pending.scan_prefix(key_prefix(symbol)) {
+            let (_, value) = entry?;
+            match serde_json::from_slice::<OrderBookUpdate>(&value) {
+                Ok(update) => updates.push(update),
+                Err(err) => eprintln!(
+                    "{}",
+                    format!("Durable queue: failed to decode a persisted update: {:?}", err).red().bold()
+                ),
+            }
+        }
+        debug_print!("Durable queue: reloaded {} pending update(s) for {}", updates.len(), symbol);
+        Ok(updates)
+    }
+
+    // `first_trade_id` of the earliest update (lowest `last_trade_id`) still durably queued
+    // for `symbol`, if any - used on startup to
+    // decide whether the pending range already covers `last_applied_id + 1`, in which case
+    // recovery can resume straight from the queue instead of re-fetching a snapshot.
+    pub fn lowest_pending_first_trade_id(&self, symbol: &str) -> sled::Result<Option<u64>> {
+        let prefix = key_prefix(symbol);
+        match self.pending.scan_prefix(&prefix).next() {
+            Some(entry) => {
+                let (_, value) = entry?;
+                let update: OrderBookUpdate = serde_json::from_slice(&value)
+                    .expect("durably-queued updates were serialized by this same code");
+                Ok(Some(update.first_trade_id))
+            }
+            None => Ok(None),
+        }
+    }
+}
@@ -1,11 +1,14 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap};
 use colored::Colorize;
 
+use crate::durable_queue::DurableQueue;
+use crate::events::{self, MarketEvent};
 use crate::messages::OrderBookUpdate;
-use crate::order_book::{InstrumentState, OrderBook};
+use crate::order_book::{InstrumentState, StateLock, SymbolFilters};
 use crate::recovery::TimeoutState;
 use crate::debug_print;
 
@@ -31,110 +34,230 @@ impl PartialEq for PrioritizedOrderBookUpdate {
     }
 }
 
+// Everything the dispatcher tracks for one symbol: its own pending-update heap plus the
+// `StateLock`/`TimeoutState` pair that updates for this symbol alone are applied against.
+// Living behind its own `RwLock` (see `EventBuffer::symbols`) means a slow recovery on one
+// symbol only ever blocks dispatch of *this* symbol's updates, never another's.
+struct SymbolEntry {
+    symbol: String,
+    buffer: BinaryHeap<PrioritizedOrderBookUpdate>,
+    state_lock: Arc<StateLock>,
+    timeout_state: Arc<TimeoutState>,
+}
+
 // Event buffer is used for storing events from the net. The first received consequent event will
 // be applied immediately, past updates - ignored and future updates will be buffered for future publishing
+//
+// A single `EventBuffer` is shared by every feed, for every tracked symbol: incoming updates
+// are routed to the correct `SymbolEntry` by `update.symbol`, so one process can maintain
+// hundreds of books through one dispatcher instead of a separate buffer/lock set per
+// instrument. The in-memory heaps are mirrored into `durable`, a single crash-recoverable
+// pending queue shared across all symbols (it's already keyed by `(symbol, update_id)`), so a
+// process restart mid-recovery doesn't lose buffered updates for any symbol.
 pub struct EventBuffer {
-    pub buffer: BinaryHeap<PrioritizedOrderBookUpdate>,
+    durable: DurableQueue,
+    symbols: RwLock<HashMap<String, Arc<RwLock<SymbolEntry>>>>,
 }
 
 impl EventBuffer {
-    pub fn new() -> Self {
-        Self {
-            buffer: BinaryHeap::new(),
+    // Opens (or creates) the durable queue at `db_path`. Symbols are added afterwards via
+    // `register_symbol`, which reloads whatever that symbol left pending from a previous run.
+    pub fn open(db_path: &str) -> sled::Result<Self> {
+        Ok(Self {
+            durable: DurableQueue::open(db_path)?,
+            symbols: RwLock::new(HashMap::new()),
+        })
+    }
+
+    // Starts tracking `symbol` if it isn't already, reloading any updates left pending in the
+    // durable queue from a previous run straight into its in-memory heap, and returns the
+    // `(state_lock, timeout_state)` pair callers thread through to feeds/recovery/validator/
+    // scrub for that symbol. Calling this again for an already-tracked symbol just returns its
+    // existing pair.
+    pub async fn register_symbol(
+        &self,
+        symbol: &str,
+        filters: SymbolFilters,
+        timeout_duration: Duration,
+    ) -> sled::Result<(Arc<StateLock>, Arc<TimeoutState>)> {
+        let symbol = symbol.to_lowercase();
+        let mut symbols = self.symbols.write().await;
+        if let Some(entry) = symbols.get(&symbol) {
+            let entry = entry.read().await;
+            return Ok((Arc::clone(&entry.state_lock), Arc::clone(&entry.timeout_state)));
+        }
+
+        let mut buffer = BinaryHeap::new();
+        for update in self.durable.load_pending(&symbol)? {
+            buffer.push(PrioritizedOrderBookUpdate(update));
         }
+
+        let state_lock = Arc::new(StateLock::new(filters));
+        let timeout_state = Arc::new(TimeoutState::new(timeout_duration));
+        let handles = (Arc::clone(&state_lock), Arc::clone(&timeout_state));
+
+        symbols.insert(symbol.clone(), Arc::new(RwLock::new(SymbolEntry {
+            symbol,
+            buffer,
+            state_lock,
+            timeout_state,
+        })));
+
+        Ok(handles)
     }
 
-    // add an update to the buffer
+    // Lowest `first_trade_id` still durably queued for `symbol`, if any. Used on startup to
+    // decide whether the reloaded pending range already covers `last_applied_id + 1`, in
+    // which case recovery can resume straight from the queue instead of re-fetching a snapshot.
+    pub async fn lowest_pending_first_trade_id(&self, symbol: &str) -> sled::Result<Option<u64>> {
+        self.durable.lowest_pending_first_trade_id(symbol)
+    }
+
+    // Routes `update` to its symbol's own buffer/state and applies whatever of it is ready,
+    // taking only that symbol's lock - an unrelated symbol's updates are never blocked by this.
     pub async fn buffer_and_process_update(
-        &mut self,
+        &self,
         update: OrderBookUpdate,
-        order_book: Arc<RwLock<OrderBook>>,
-        state: Arc<RwLock<InstrumentState>>,
-        timeout_state: Arc<TimeoutState>,
+        event_sender: broadcast::Sender<MarketEvent>,
     ) {
+        let Some(entry) = self.entry(&update.symbol).await else {
+            eprintln!("{}", format!(
+                "Event buffer: received update for unregistered symbol {}, dropping", update.symbol
+            ).red().bold());
+            return;
+        };
+
+        let mut entry = entry.write().await;
         debug_print!("Buffering update with first_trade_id = {}", update.first_trade_id);
-        self.buffer.push(PrioritizedOrderBookUpdate(update));
+        if let Err(err) = self.durable.push(&entry.symbol, &update) {
+            eprintln!("{}", format!("Durable queue: failed to persist update before buffering: {:?}", err).red().bold());
+        }
+        entry.buffer.push(PrioritizedOrderBookUpdate(update));
 
-        self.process_buffered_updates(order_book, state, Arc::clone(&timeout_state)).await;
+        self.drain_ready(&mut *entry, event_sender).await;
     }
 
-    pub async fn process_buffered_updates(
-        &mut self,
-        order_book: Arc<RwLock<OrderBook>>,
-        state: Arc<RwLock<InstrumentState>>,
-        timeout_state: Arc<TimeoutState>,
-    ) {
+    // Applies whichever updates at the head of `symbol`'s buffer are ready against its current
+    // state, e.g. right after recovery fetched a fresh snapshot.
+    pub async fn process_buffered_updates(&self, symbol: &str, event_sender: broadcast::Sender<MarketEvent>) {
+        if let Some(entry) = self.entry(symbol).await {
+            let mut entry = entry.write().await;
+            self.drain_ready(&mut *entry, event_sender).await;
+        }
+    }
+
+    // Case-insensitive lookup: wire updates carry Binance's uppercase `"s"` field
+    // (e.g. `"BTCUSDT"`) while symbols are registered under the lowercase form used
+    // throughout the rest of the codebase (stream names, snapshot URLs, `SYMBOLS`) - normalize
+    // here, at the one place every dispatch path funnels through, rather than at each call site.
+    async fn entry(&self, symbol: &str) -> Option<Arc<RwLock<SymbolEntry>>> {
+        self.symbols.read().await.get(&symbol.to_lowercase()).cloned()
+    }
+
+    async fn drain_ready(&self, entry: &mut SymbolEntry, event_sender: broadcast::Sender<MarketEvent>) {
         loop {
             let mut process_next_update = true;
 
-            let next_update = {
-                let mut state_lock = state.write().await;
-                let book = order_book.write().await;
-
-                if self.buffer.is_empty() {
-                    debug_print!("No updates in the buffer.");
-                    process_next_update = false; // Exit the loop
-                    None
-                } else if let Some(PrioritizedOrderBookUpdate(update)) = self.buffer.peek() {
-                    if *state_lock == InstrumentState::JustRecovered {
-                        // in JustRecovered state, take updates in range
-                        if update.first_trade_id <= book.last_applied_id + 1 && update.last_trade_id >= book.last_applied_id + 1 {
-                            debug_print!("Taking update after recovery: first_trade_id = {}, last_trade_id = {}",
-                                update.first_trade_id, update.last_trade_id);
-                            *state_lock = InstrumentState::Normal;
-                            debug_print!("State set to Normal after processing.");
-                            self.buffer.pop().map(|entry| entry.0) // remove and process the update
-                        } else if update.first_trade_id > book.last_applied_id + 1 {
-                            debug_print!(
-                                "Future update detected after recovery: first_trade_id ={}, waiting for prior updates. Last applied ID={}",
-                                update.first_trade_id, book.last_applied_id
-                            );
-                            process_next_update = false; // stop processing further updates
-                            None
-                        } else {
-                            debug_print!(
-                                "Outdated update after recovery: U={}, removing from buffer. Last Applied ID={}",
-                                update.first_trade_id, book.last_applied_id
-                            );
-                            self.buffer.pop(); // remove outdated update
-                            None
-                        }
+            // cheap, lock-free read of the current book+state pair to decide what to do
+            // with the head of the buffer
+            let snapshot = entry.state_lock.load();
+
+            let next_update = if entry.buffer.is_empty() {
+                debug_print!("No updates in the buffer for {}.", entry.symbol);
+                process_next_update = false; // Exit the loop
+                None
+            } else if let Some(PrioritizedOrderBookUpdate(update)) = entry.buffer.peek() {
+                if snapshot.state == InstrumentState::JustRecovered {
+                    // in JustRecovered state, take updates in range
+                    if update.first_trade_id <= snapshot.book.last_applied_id + 1 && update.last_trade_id >= snapshot.book.last_applied_id + 1 {
+                        debug_print!("Taking update after recovery: first_trade_id = {}, last_trade_id = {}",
+                            update.first_trade_id, update.last_trade_id);
+                        self.pop_and_forget_durably(entry) // remove and process the update
+                    } else if update.first_trade_id > snapshot.book.last_applied_id + 1 {
+                        debug_print!(
+                            "Future update detected after recovery: first_trade_id ={}, waiting for prior updates. Last applied ID={}",
+                            update.first_trade_id, snapshot.book.last_applied_id
+                        );
+                        process_next_update = false; // stop processing further updates
+                        None
                     } else {
-                        // normal state: take only consecutive updates
-                        if update.first_trade_id == book.last_applied_id + 1 {
-                            debug_print!("Taking consecutive update: first_trade_id = {}, last_trade_id = {}",
-                                update.first_trade_id, update.last_trade_id);
-                            self.buffer.pop().map(|entry| entry.0) // remove and process the update
-                        } else if update.first_trade_id > book.last_applied_id + 1 {
-                            debug_print!(
-                                "Future update detected: first_trade_id = {}, waiting for prior updates. Last Applied ID={}",
-                                update.first_trade_id, book.last_applied_id
-                            );
-                            process_next_update = false; // stop processing further updates
-                            None
-                        } else {
-                            debug_print!(
-                                "Outdated update detected: first_trade_id = {}, removing from buffer. Last Applied ID={}",
-                                update.first_trade_id, book.last_applied_id
-                            );
-                            self.buffer.pop(); // remove outdated update
-                            None
-                        }
+                        debug_print!(
+                            "Outdated update after recovery: U={}, removing from buffer. Last Applied ID={}",
+                            update.first_trade_id, snapshot.book.last_applied_id
+                        );
+                        self.pop_and_forget_durably(entry); // remove outdated update
+                        None
                     }
                 } else {
-                    debug_print!("No updates in the buffer.");
-                    None
+                    // normal state: take only consecutive updates
+                    if update.first_trade_id == snapshot.book.last_applied_id + 1 {
+                        debug_print!("Taking consecutive update: first_trade_id = {}, last_trade_id = {}",
+                            update.first_trade_id, update.last_trade_id);
+                        self.pop_and_forget_durably(entry) // remove and process the update
+                    } else if update.first_trade_id > snapshot.book.last_applied_id + 1 {
+                        debug_print!(
+                            "Future update detected: first_trade_id = {}, waiting for prior updates. Last Applied ID={}",
+                            update.first_trade_id, snapshot.book.last_applied_id
+                        );
+                        process_next_update = false; // stop processing further updates
+                        None
+                    } else {
+                        debug_print!(
+                            "Outdated update detected: first_trade_id = {}, removing from buffer. Last Applied ID={}",
+                            update.first_trade_id, snapshot.book.last_applied_id
+                        );
+                        self.pop_and_forget_durably(entry); // remove outdated update
+                        None
+                    }
                 }
+            } else {
+                debug_print!("No updates in the buffer for {}.", entry.symbol);
+                None
             };
 
             if let Some(update) = next_update {
-                if let Err(err) = OrderBook::apply_update_locked(&order_book, &update).await {
-                    eprintln!("{}",
-                        format!("Error applying buffered update: {}. Update ID: {:?}",
-                        err, update.first_trade_id).red().bold());
-                } else {
-                    // reset the inactivity timer on successful update
-                    timeout_state.reset().await;
+                // transitioning out of JustRecovered and applying the update are published as
+                // a single atomic swap, so no reader can observe one without the other
+                let was_just_recovered = snapshot.state == InstrumentState::JustRecovered;
+                let result = entry.state_lock.update(|current| {
+                    if was_just_recovered {
+                        current.state = InstrumentState::Normal;
+                        debug_print!("State set to Normal after processing.");
+                    }
+                    current.book.apply_update(&update)
+                });
+
+                match result {
+                    Ok(Some((top_of_book, level_changes))) => {
+                        // reset the inactivity timer on successful update
+                        entry.timeout_state.reset().await;
+                        // published under entry.symbol (the registered, lowercase form), not
+                        // update.symbol - the wire payload's case ("BTCUSDT") would otherwise
+                        // leak into events while every other MarketEvent variant (published
+                        // from recovery.rs/combined_stream.rs) uses the lowercase form
+                        for change in level_changes {
+                            events::publish(&event_sender, MarketEvent::LevelUpdate {
+                                symbol: entry.symbol.clone(),
+                                side: change.side,
+                                price: change.price,
+                                new_qty: change.new_qty,
+                                removed: change.removed,
+                                last_applied_id: update.last_trade_id,
+                            });
+                        }
+                        events::publish(&event_sender, MarketEvent::OrderBookDelta {
+                            symbol: entry.symbol.clone(),
+                            first_trade_id: update.first_trade_id,
+                            last_trade_id: update.last_trade_id,
+                            top_of_book,
+                        });
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        eprintln!("{}",
+                            format!("Error applying buffered update: {}. Update ID: {:?}",
+                            err, update.first_trade_id).red().bold());
+                    }
                 }
             }
 
@@ -144,4 +267,70 @@ impl EventBuffer {
             }
         }
     }
-}
\ No newline at end of file
+
+    // Pops the head of the in-memory heap and removes its durable counterpart, since
+    // whichever caller popped it has either already applied it or decided to discard it.
+    fn pop_and_forget_durably(&self, entry: &mut SymbolEntry) -> Option<OrderBookUpdate> {
+        let update = entry.buffer.pop().map(|prioritized| prioritized.0)?;
+        if let Err(err) = self.durable.remove(&entry.symbol, update.last_trade_id) {
+            eprintln!("{}", format!("Durable queue: failed to remove applied update: {:?}", err).red().bold());
+        }
+        Some(update)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order_book::SymbolFilters;
+
+    fn test_filters() -> SymbolFilters {
+        SymbolFilters::new("0.01", "0.00001")
+    }
+
+    // Binance's wire payloads always carry the symbol uppercase (the "s" field), while every
+    // symbol in this codebase is registered lowercase (stream names, snapshot URLs, `SYMBOLS`
+    // in main.rs). A depth update should still reach the registered entry regardless of case.
+    #[tokio::test]
+    async fn dispatches_uppercase_wire_symbol_to_lowercase_registered_entry() {
+        let db_path = std::env::temp_dir()
+            .join(format!("event_buffer_test_{}_{}", std::process::id(), line!()));
+        let event_buffer = EventBuffer::open(db_path.to_str().unwrap())
+            .expect("failed to open test event buffer");
+
+        event_buffer.register_symbol("btcusdt", test_filters(), Duration::from_secs(5)).await
+            .expect("failed to register symbol");
+
+        let raw = r#"{
+            "e": "depthUpdate",
+            "E": 123456789,
+            "s": "BTCUSDT",
+            "U": 1,
+            "u": 1,
+            "b": [],
+            "a": []
+        }"#;
+        let update: OrderBookUpdate = serde_json::from_str(raw).expect("failed to parse update");
+
+        let (event_sender, mut event_receiver) = broadcast::channel(16);
+        event_buffer.buffer_and_process_update(update, event_sender).await;
+
+        let registered = event_buffer.symbols.read().await;
+        let entry = registered.get("btcusdt").expect("symbol should still be registered");
+        let entry = entry.read().await;
+        assert!(entry.buffer.is_empty(), "update should have been applied, not stuck in the buffer under the wrong key");
+
+        drop(entry);
+        drop(registered);
+
+        // the published event must carry the registered (lowercase) symbol, not the wire's
+        // uppercase one, so it matches the rest of the MarketEvent variants
+        let published = event_receiver.recv().await.expect("expected an OrderBookDelta to be published");
+        match published {
+            MarketEvent::OrderBookDelta { symbol, .. } => assert_eq!(symbol, "btcusdt"),
+            other => panic!("expected OrderBookDelta, got {:?}", other),
+        }
+
+        let _ = std::fs::remove_dir_all(&db_path);
+    }
+}
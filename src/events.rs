@@ -0,0 +1,101 @@
+use colored::Colorize;
+use tokio::sync::broadcast;
+
+use crate::messages::{AggTrade, BestDeal, BookTicker, KlineEvent, MarkPrice, Ticker24hr};
+use crate::order_book::{Side, TopOfBook};
+
+// Lagging behind the broadcast channel by more than this many events drops the oldest ones;
+// the pretty printer is a "nice to have" subscriber so losing some history under load is
+// preferable to applying backpressure to the feeds themselves.
+pub const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+// Typed events published by the feeds, so the crate can be used as a data source for a
+// strategy or GUI instead of only ever printing to the terminal.
+#[derive(Debug, Clone)]
+pub enum MarketEvent {
+    // An incremental order book change, paired with the resulting top-of-book so a
+    // subscriber can reconcile its view without maintaining a full book of its own.
+    OrderBookDelta { symbol: String, first_trade_id: u64, last_trade_id: u64, top_of_book: TopOfBook },
+    // A full snapshot was just applied during recovery; subscribers should treat this as
+    // the new baseline.
+    BookSnapshotApplied { symbol: String, last_applied_id: u64, top_of_book: TopOfBook },
+    // A single price level changed while applying an update, published alongside
+    // `OrderBookDelta` so a subscriber that only cares about one side/level doesn't have to
+    // reconstruct it from the top-of-book view.
+    LevelUpdate { symbol: String, side: Side, price: f64, new_qty: f64, removed: bool, last_applied_id: u64 },
+    // A full-depth snapshot of the book, published each time recovery applies a fresh
+    // snapshot, so a late subscriber can
+    // bootstrap its own copy and then apply subsequent `LevelUpdate`/`OrderBookDelta`
+    // events, detecting gaps by comparing against `last_applied_id`.
+    BookCheckpoint { symbol: String, last_applied_id: u64, bids: Vec<(f64, f64)>, asks: Vec<(f64, f64)> },
+    BestDeal { symbol: String, best_deal: BestDeal },
+    AggTrade(AggTrade),
+    Kline(KlineEvent),
+    Ticker24hr(Ticker24hr),
+    BookTicker(BookTicker),
+    MarkPrice(MarkPrice),
+}
+
+pub fn channel() -> (broadcast::Sender<MarketEvent>, broadcast::Receiver<MarketEvent>) {
+    broadcast::channel(EVENT_CHANNEL_CAPACITY)
+}
+
+// Publishing is best-effort: with no subscribers left `send` returns an error that we don't
+// care about, the feeds themselves don't depend on anyone listening.
+pub fn publish(sender: &broadcast::Sender<MarketEvent>, event: MarketEvent) {
+    let _ = sender.send(event);
+}
+
+fn print_top_of_book(top_of_book: &TopOfBook) {
+    writeln_blue("Bids:");
+    for (price, qty) in top_of_book.bids.iter().take(5) {
+        writeln_blue(&format!("  Price: {}, Qty: {}", price, qty));
+    }
+    writeln_blue("Asks:");
+    for (price, qty) in top_of_book.asks.iter().take(5) {
+        writeln_blue(&format!("  Price: {}, Qty: {}", price, qty));
+    }
+}
+
+fn writeln_blue(line: &str) {
+    println!("{}", line.blue().bold());
+}
+
+// Optional subscriber that reproduces the colored console output the feeds used to print
+// directly. Kept around for interactive/debug use; real consumers should subscribe to the
+// broadcast channel themselves instead.
+pub async fn run_pretty_printer(mut receiver: broadcast::Receiver<MarketEvent>) {
+    loop {
+        match receiver.recv().await {
+            Ok(MarketEvent::OrderBookDelta { symbol, first_trade_id, last_trade_id, top_of_book }) => {
+                writeln_blue(&format!("Order Book update [{}] ({}..{}):", symbol, first_trade_id, last_trade_id));
+                print_top_of_book(&top_of_book);
+            }
+            Ok(MarketEvent::BookSnapshotApplied { symbol, last_applied_id, top_of_book }) => {
+                writeln_blue(&format!("Order Book snapshot applied [{}] (last_applied_id={}):", symbol, last_applied_id));
+                print_top_of_book(&top_of_book);
+            }
+            Ok(MarketEvent::LevelUpdate { symbol, side, price, new_qty, removed, last_applied_id }) => {
+                let side = match side { Side::Bid => "Bid", Side::Ask => "Ask" };
+                writeln_blue(&format!(
+                    "Level update [{}] (last_applied_id={}): {} @ {} -> {}",
+                    symbol, last_applied_id, side, price, if removed { "removed".to_string() } else { new_qty.to_string() }
+                ));
+            }
+            Ok(MarketEvent::BookCheckpoint { symbol, last_applied_id, bids, asks }) => {
+                writeln_blue(&format!("Order Book checkpoint [{}] (last_applied_id={}, {} bids, {} asks):", symbol, last_applied_id, bids.len(), asks.len()));
+                print_top_of_book(&TopOfBook { bids, asks });
+            }
+            Ok(MarketEvent::BestDeal { best_deal, .. }) => println!("{}", best_deal),
+            Ok(MarketEvent::AggTrade(trade)) => println!("{}", trade),
+            Ok(MarketEvent::Kline(kline)) => println!("{}", kline),
+            Ok(MarketEvent::Ticker24hr(ticker)) => println!("{}", ticker),
+            Ok(MarketEvent::BookTicker(book_ticker)) => println!("{}", book_ticker),
+            Ok(MarketEvent::MarkPrice(mark_price)) => println!("{}", mark_price),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                eprintln!("{}", format!("Pretty printer: lagged behind, skipped {} events", skipped).red().bold());
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
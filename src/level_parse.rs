@@ -0,0 +1,13 @@
+// Shared by the book validator and scrubber, which both compare the locally built book
+// against price/qty levels pulled from an untrusted source (a REST snapshot or depth5
+// payload), each represented as a `[price, qty]` string pair.
+
+// Parses a `[price, qty]` string pair into floats, returning a description of what failed to
+// parse instead of silently discarding it. A malformed or empty level from a flaky REST
+// response must surface as "couldn't verify this level", never be swallowed as "this level
+// matched" - the whole point of the callers is to catch corruption, not wave it through.
+pub fn parse_level(price: &str, qty: &str) -> Result<(f64, f64), String> {
+    let price: f64 = price.parse().map_err(|_| format!("invalid price {:?}", price))?;
+    let qty: f64 = qty.parse().map_err(|_| format!("invalid qty {:?}", qty))?;
+    Ok((price, qty))
+}
@@ -7,86 +7,174 @@ mod order_book;
 mod messages;
 mod recovery;
 mod event_buffer;
+mod durable_queue;
 mod debug;
-mod best_deal;
-mod agg_trade;
+mod backoff;
+mod combined_stream;
 mod depth_feed;
+mod events;
+mod validator;
+mod scrub;
+mod level_parse;
+mod shutdown;
 
-use tokio::sync::{RwLock};
-use futures_util::{StreamExt, future, SinkExt};
-use reqwest;
+use tokio::sync::RwLock;
+use futures_util::future;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::time::Duration;
 use colored::Colorize;
 
 
-use order_book::{OrderBook, InstrumentState};
-use recovery::{monitor_and_recover, TimeoutState};
+use recovery::{fetch_symbol_filters, monitor_and_recover};
 use event_buffer::EventBuffer;
 use crate::debug::is_debug_mode;
-use crate::best_deal::start_best_deal_feed;
-use crate::depth_feed::start_depth_feed;
-use crate::agg_trade::start_aggtrade_feed;
+use crate::combined_stream::start_combined_feed;
+use crate::depth_feed::start_depth_feed_pool;
+use crate::events::run_pretty_printer;
+use crate::messages::BestDeal;
+use crate::scrub::run_book_scrubber;
+use crate::validator::run_book_validator;
+
+// Every instrument this process maintains a book for. One shared `EventBuffer` dispatches
+// updates for all of them by symbol key (see `event_buffer::EventBuffer`), so adding another
+// instrument here is the only thing needed to track it - no extra buffer or lock set per
+// symbol is wired up by hand.
+const SYMBOLS: &[&str] = &["btcusdt"];
 
 #[tokio::main]
 async fn main() {
     let debug_enabled = is_debug_mode();
     println!("Debug mode is {}", if debug_enabled { "enabled" } else { "disabled" });
 
-    let symbol = "btcusdt";
-    // number of ws connections for the same instrument for incremental feed, took a random value
+    // number of ws connections per instrument for incremental feed, took a random value
     let num_connections = 3;
-    // full depth (L2) market data ws
-    let l2_url = format!("wss://stream.binance.com:9443/ws/{}@depth", symbol);
-    // aggregated trades feed
-    let aggtrade_url = format!("wss://stream.binance.com:9443/ws/{}@aggTrade", symbol);
-    // here we subscribe to 1st level only to get the best deal feed
-    let best_deal_url = format!("wss://stream.binance.com:9443/ws/{}@depth5", symbol);
-
-    // snapshot is used for restoring the book state at the beginning and in case of loosing data
-    // from incremental feeds
-    let snapshot_url = format!(
-        "https://api.binance.com/api/v3/depth?symbol={}", symbol.to_uppercase());
-
-    // order book - current state of the market for symbol
-    let order_book = Arc::new(RwLock::new(OrderBook::new()));
-    // event buffer is used to store updates
-    let event_buffer = Arc::new(RwLock::new(EventBuffer::new()));
-    // flag to indicate the state of an instrument
-    let state = Arc::new(RwLock::new(InstrumentState::JustStarted));
-    // timeout state with a 5-second duration for triggering recovery in case of inactivity
-    let timeout_state = Arc::new(TimeoutState::new(Duration::from_secs(5)));
-
-    // start monitoring and recovery
-    let monitor_handle = tokio::spawn(monitor_and_recover(
-        Arc::clone(&event_buffer),
-        Arc::clone(&order_book),
-        Arc::clone(&state),
-        snapshot_url.clone(),
-        Arc::clone(&timeout_state),
-    ));
-
-    // start publishing trades
-    let aggtrade_handle = tokio::spawn(start_aggtrade_feed(aggtrade_url));
-    // and best deals
-    let best_deal_handle = tokio::spawn(start_best_deal_feed(best_deal_url));
-
-    let mut tasks = vec![];
-    for connection_id in 0..num_connections {
-        let event_buffer = Arc::clone(&event_buffer);
-        let order_book = Arc::clone(&order_book);
-        let state = Arc::clone(&state);
-        let l2_url = l2_url.clone();
-        let timeout_state = Arc::clone(&timeout_state);
-
-        tasks.push(tokio::spawn(async move {
-            start_depth_feed(l2_url, event_buffer, order_book, state, connection_id, timeout_state).await;
-        }));
+
+    // event buffer is shared by every tracked symbol; it dispatches each update to its own
+    // symbol's buffer/state and is backed by a single durable queue on disk (already keyed by
+    // symbol) so buffered updates survive a restart instead of forcing a fresh snapshot every time
+    let event_buffer = Arc::new(
+        EventBuffer::open("event_buffer_db").expect("failed to open durable event buffer"),
+    );
+    // latest depth5 ("best deal") snapshot per symbol, kept around so each symbol's book
+    // validator can cross-check it against that symbol's independently-built order book
+    let latest_best_deals: Arc<RwLock<HashMap<String, BestDeal>>> = Arc::new(RwLock::new(HashMap::new()));
+
+    // fanned out to every feed, the monitor loops and the reconnection supervisors so Ctrl-C
+    // winds the whole program down deterministically instead of requiring a SIGKILL
+    let (shutdown_sender, shutdown_receiver) = shutdown::channel();
+    tokio::spawn(async move {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            eprintln!("{}", format!("Failed to listen for Ctrl-C: {:?}", err).red().bold());
+            return;
+        }
+        println!("Ctrl-C received, shutting down...");
+        shutdown_sender.shutdown();
+    });
+
+    // every feed publishes typed MarketEvents here instead of printing directly; the
+    // colored console output below is just one (optional) subscriber
+    let (event_sender, event_receiver) = events::channel();
+    let pretty_printer_handle = tokio::spawn(run_pretty_printer(event_receiver));
+
+    // aggregated trades and the top-of-book (depth5) "best deal" feeds for every tracked
+    // symbol don't need arbitration, so they all share one combined-stream connection with
+    // runtime subscribe/unsubscribe
+    let mut combined_streams = Vec::with_capacity(SYMBOLS.len() * 2);
+    let mut tasks = vec![pretty_printer_handle];
+
+    for &symbol in SYMBOLS {
+        // full depth (L2) market data ws, still opened as N separate per-connection streams
+        // below for arbitration
+        let l2_url = format!("wss://stream.binance.com:9443/ws/{}@depth", symbol);
+        combined_streams.push(format!("{}@aggTrade", symbol));
+        combined_streams.push(format!("{}@depth5", symbol));
+
+        // snapshot is used for restoring the book state at the beginning and in case of
+        // loosing data from incremental feeds
+        let snapshot_url = format!(
+            "https://api.binance.com/api/v3/depth?symbol={}", symbol.to_uppercase());
+
+        // tick/lot size for this symbol, fetched once from exchangeInfo so the book can be
+        // keyed on fixed-point ticks/lots instead of parsing and comparing floats on every update
+        let symbol_filters = fetch_symbol_filters(&symbol.to_uppercase()).await
+            .unwrap_or_else(|err| panic!("failed to fetch exchangeInfo tick/lot size for {}: {}", symbol, err));
+
+        // registers this symbol with the shared dispatcher (reloading anything left pending
+        // in the durable queue from a previous run) and hands back the StateLock/TimeoutState
+        // pair every feed/recovery/validator/scrubber task for this symbol reads from directly
+        let (state_lock, timeout_state) = event_buffer
+            .register_symbol(symbol, symbol_filters, Duration::from_secs(5)).await
+            .expect("failed to register symbol with event buffer");
+
+        // start monitoring and recovery
+        tasks.push(tokio::spawn(monitor_and_recover(
+            symbol.to_string(),
+            Arc::clone(&event_buffer),
+            Arc::clone(&state_lock),
+            snapshot_url.clone(),
+            Arc::clone(&timeout_state),
+            event_sender.clone(),
+            shutdown_receiver.clone(),
+        )));
+
+        // periodically cross-checks the built order book against the latest depth5 snapshot
+        // and forces a recovery if they've diverged, instead of relying solely on the
+        // inactivity timer
+        tasks.push(tokio::spawn(run_book_validator(
+            symbol.to_string(),
+            Arc::clone(&state_lock),
+            Arc::clone(&latest_best_deals),
+            Arc::clone(&event_buffer),
+            snapshot_url.clone(),
+            Arc::clone(&timeout_state),
+            event_sender.clone(),
+            shutdown_receiver.clone(),
+        )));
+
+        // periodically fetches a fresh REST depth snapshot and scrubs the built book against
+        // it level-by-level, catching silent corruption (e.g. a level dropped to zero without
+        // ever going through a zero-qty update) that the first/last_update_id continuity
+        // checks in apply_update can't see on their own
+        tasks.push(tokio::spawn(run_book_scrubber(
+            symbol.to_string(),
+            Arc::clone(&state_lock),
+            Arc::clone(&event_buffer),
+            snapshot_url.clone(),
+            Arc::clone(&timeout_state),
+            event_sender.clone(),
+            shutdown_receiver.clone(),
+        )));
+
+        // all num_connections arbitration connections for this symbol's depth feed are
+        // polled together in a single task instead of one task (and lock set) per connection
+        tasks.push(tokio::spawn(start_depth_feed_pool(
+            symbol.to_string(),
+            l2_url,
+            num_connections,
+            Arc::clone(&event_buffer),
+            Arc::clone(&state_lock),
+            snapshot_url.clone(),
+            Arc::clone(&timeout_state),
+            event_sender.clone(),
+            shutdown_receiver.clone(),
+        )));
     }
 
+    // combined stream carrying aggregated trades and best-deal (depth5) updates for every
+    // tracked symbol over one socket; the returned handle can add/drop subscriptions later
+    // without reconnecting
+    let combined_stream_handle = start_combined_feed(
+        combined_streams,
+        Arc::clone(&event_buffer),
+        event_sender.clone(),
+        Arc::clone(&latest_best_deals),
+        shutdown_receiver.clone(),
+    ).await;
+    // example of reconfiguring subscriptions at runtime without reopening the connection:
+    // combined_stream_handle.subscribe(vec!["ethusdt@aggTrade".to_string()]);
+    let _ = &combined_stream_handle;
+
     // Wait for all tasks to complete
-    tasks.push(monitor_handle);
-    tasks.push(aggtrade_handle);
-    tasks.push(best_deal_handle);
     future::join_all(tasks).await;
 }
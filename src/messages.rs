@@ -2,13 +2,15 @@ use std::fmt;
 use colored::Colorize;
 use futures_util::SinkExt;
 use futures_util::stream::SplitSink;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tokio::net::TcpStream;
 use tokio_tungstenite::tungstenite::Message;
 use tokio_tungstenite::WebSocketStream;
 
 // Order book price and quantity depth updates from Depth Stream (L2) <symbol>@depth
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+// Serialize is needed alongside Deserialize so the durable event queue can round-trip an
+// update back to disk while it's waiting to be applied.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct OrderBookUpdate {
     #[serde(alias="e")]
     pub event_type: String,
@@ -37,8 +39,22 @@ pub struct Snapshot {
     pub asks: Vec<[String; 2]>,
 }
 
+// GET /api/v3/exchangeInfo response, trimmed to what's needed to look up a symbol's tick
+// and lot size - individual filter shapes vary by `filterType`, so each is left as a raw
+// JSON value and picked apart by the caller instead of modeling every filter type.
+#[derive(Debug, Deserialize)]
+pub struct ExchangeInfo {
+    pub symbols: Vec<SymbolInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SymbolInfo {
+    pub symbol: String,
+    pub filters: Vec<serde_json::Value>,
+}
+
 // Aggregated Trades feed message structure
-#[derive(Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct AggTrade {
     #[serde(alias="e")]
     pub event_type: String,
@@ -72,7 +88,7 @@ impl fmt::Display for AggTrade {
 }
 
 // Best Deal message structure
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BestDeal {
     pub last_update_id: u64,          // Last processed event ID
@@ -103,6 +119,184 @@ impl fmt::Display for BestDeal {
     }
 }
 
+// Candlestick payload nested inside a kline event, <symbol>@kline_<interval>
+#[derive(Debug, Clone, Deserialize)]
+pub struct Kline {
+    #[serde(alias="t")]
+    pub start_time: u64,
+    #[serde(alias="T")]
+    pub close_time: u64,
+    #[serde(alias="i")]
+    pub interval: String,
+    #[serde(alias="o")]
+    pub open: String,
+    #[serde(alias="c")]
+    pub close: String,
+    #[serde(alias="h")]
+    pub high: String,
+    #[serde(alias="l")]
+    pub low: String,
+    #[serde(alias="v")]
+    pub volume: String,
+    #[serde(alias="x")]
+    pub is_closed: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KlineEvent {
+    #[serde(alias="e")]
+    pub event_type: String,
+    #[serde(alias="E")]
+    pub event_time: u64,
+    #[serde(alias="s")]
+    pub symbol: String,
+    #[serde(alias="k")]
+    pub kline: Kline,
+}
+
+impl fmt::Display for KlineEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", format!(
+            "Kline [{}] {} - O: {}, H: {}, L: {}, C: {}, V: {}, Closed: {}",
+            self.symbol, self.kline.interval, self.kline.open, self.kline.high,
+            self.kline.low, self.kline.close, self.kline.volume, self.kline.is_closed,
+        ).yellow().bold())
+    }
+}
+
+// 24hr rolling window ticker, <symbol>@ticker
+#[derive(Debug, Clone, Deserialize)]
+pub struct Ticker24hr {
+    #[serde(alias="e")]
+    pub event_type: String,
+    #[serde(alias="E")]
+    pub event_time: u64,
+    #[serde(alias="s")]
+    pub symbol: String,
+    #[serde(alias="p")]
+    pub price_change: String,
+    #[serde(alias="P")]
+    pub price_change_percent: String,
+    #[serde(alias="c")]
+    pub last_price: String,
+    #[serde(alias="o")]
+    pub open_price: String,
+    #[serde(alias="h")]
+    pub high_price: String,
+    #[serde(alias="l")]
+    pub low_price: String,
+    #[serde(alias="v")]
+    pub volume: String,
+    #[serde(alias="q")]
+    pub quote_volume: String,
+}
+
+impl fmt::Display for Ticker24hr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", format!(
+            "24hr Ticker [{}] Last: {}, Change: {} ({}%), High: {}, Low: {}, Volume: {}",
+            self.symbol, self.last_price, self.price_change, self.price_change_percent,
+            self.high_price, self.low_price, self.volume,
+        ).green().bold())
+    }
+}
+
+// Best bid/ask stream, <symbol>@bookTicker. Unlike the other streams this payload has no
+// event_type/event_time fields.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BookTicker {
+    #[serde(alias="u")]
+    pub update_id: u64,
+    #[serde(alias="s")]
+    pub symbol: String,
+    #[serde(alias="b")]
+    pub best_bid_price: String,
+    #[serde(alias="B")]
+    pub best_bid_qty: String,
+    #[serde(alias="a")]
+    pub best_ask_price: String,
+    #[serde(alias="A")]
+    pub best_ask_qty: String,
+}
+
+impl fmt::Display for BookTicker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", format!(
+            "Book Ticker [{}] Bid: {} ({}), Ask: {} ({})",
+            self.symbol, self.best_bid_price, self.best_bid_qty, self.best_ask_price, self.best_ask_qty,
+        ).cyan().bold())
+    }
+}
+
+// Mark price stream, <symbol>@markPrice
+#[derive(Debug, Clone, Deserialize)]
+pub struct MarkPrice {
+    #[serde(alias="e")]
+    pub event_type: String,
+    #[serde(alias="E")]
+    pub event_time: u64,
+    #[serde(alias="s")]
+    pub symbol: String,
+    #[serde(alias="p")]
+    pub mark_price: String,
+    #[serde(alias="i")]
+    pub index_price: Option<String>,
+    #[serde(alias="r")]
+    pub funding_rate: Option<String>,
+    #[serde(alias="T")]
+    pub next_funding_time: Option<u64>,
+}
+
+impl fmt::Display for MarkPrice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", format!(
+            "Mark Price [{}] Mark: {}, Funding Rate: {}",
+            self.symbol, self.mark_price, self.funding_rate.as_deref().unwrap_or("n/a"),
+        ).magenta().bold())
+    }
+}
+
+// Every stream type this client understands, so a single feed loop can deserialize any
+// combined-stream payload and dispatch on the variant instead of needing to know the stream
+// type up front. Order matters for serde's untagged matching: more structurally distinctive
+// variants (shapes that can't be mistaken for one another) are tried first.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum WebsocketEvent {
+    OrderBookUpdate(OrderBookUpdate),
+    Kline(KlineEvent),
+    AggTrade(AggTrade),
+    Ticker24hr(Ticker24hr),
+    MarkPrice(MarkPrice),
+    BookTicker(BookTicker),
+    BestDeal(BestDeal),
+}
+
+// Control frame sent over a combined-stream connection to (un)subscribe at runtime, e.g.
+// {"method":"SUBSCRIBE","params":["btcusdt@depth","ethusdt@aggTrade"],"id":1}
+#[derive(Debug, Serialize)]
+pub struct StreamControlRequest {
+    pub method: ControlMethod,
+    pub params: Vec<String>,
+    pub id: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ControlMethod {
+    Subscribe,
+    Unsubscribe,
+}
+
+// Envelope wrapping every message on a combined-stream connection
+// (wss://stream.binance.com:9443/stream): the stream name ("<symbol>@<stream>") carries the
+// symbol, while `data` is dispatched by deserializing it as a `WebsocketEvent`.
+#[derive(Debug, Deserialize)]
+pub struct CombinedStreamEnvelope {
+    pub stream: String,
+    pub data: serde_json::Value,
+}
+
 // ping-pong messaging is required by web socket protocol
 pub async fn handle_ping_message(
     connection_name: &str,
@@ -1,13 +1,74 @@
 use std::collections::{BTreeMap};
 use std::fmt;
 use std::sync::Arc;
+use arc_swap::ArcSwap;
 use colored::Colorize;
-use ordered_float::OrderedFloat;
-use tokio::sync::RwLock;
+use parking_lot::Mutex;
 use crate::messages::{OrderBookUpdate, Snapshot};
 use crate::debug_print;
 
-#[derive(Debug, PartialEq)]
+// Every price/qty decimal string is parsed at this many fractional digits before being
+// reduced to a tick/lot count - generous enough to cover any Binance symbol's actual
+// precision (8 decimals covers every spot symbol today) without needing to know the
+// precision of the string up front.
+const FIXED_POINT_SCALE: usize = 8;
+const FIXED_POINT_FACTOR: i64 = 100_000_000;
+
+// Parses a decimal string as sent by Binance (e.g. "63350.12000000") into an integer
+// number of `10^-FIXED_POINT_SCALE` units, with no intermediate float - this is what keeps
+// two decimal strings that represent the same value from landing on different keys.
+fn parse_fixed_point(value: &str) -> i64 {
+    let (int_part, frac_part) = value.split_once('.').unwrap_or((value, ""));
+    let int_value: i64 = int_part.parse().unwrap();
+    let mut frac_digits = frac_part.to_string();
+    frac_digits.truncate(FIXED_POINT_SCALE);
+    while frac_digits.len() < FIXED_POINT_SCALE {
+        frac_digits.push('0');
+    }
+    let frac_value: i64 = frac_digits.parse().unwrap();
+    int_value * FIXED_POINT_FACTOR + frac_value
+}
+
+fn fixed_point_to_f64(value: i64) -> f64 {
+    value as f64 / FIXED_POINT_FACTOR as f64
+}
+
+// Per-symbol precision, fetched once from exchangeInfo at startup. Keeping both sizes as
+// fixed-point integers (at the same `FIXED_POINT_SCALE` parsed prices/qtys land on) means a
+// tick/lot count is just an exact integer division, and two price strings that round to the
+// same tick always compare equal - neither is guaranteed with `OrderedFloat<f64>` keys.
+#[derive(Debug, Clone, Copy)]
+pub struct SymbolFilters {
+    tick_size: i64,
+    lot_size: i64,
+}
+
+impl SymbolFilters {
+    pub fn new(tick_size: &str, lot_size: &str) -> Self {
+        Self {
+            tick_size: parse_fixed_point(tick_size),
+            lot_size: parse_fixed_point(lot_size),
+        }
+    }
+
+    fn price_to_ticks(&self, price: &str) -> i64 {
+        parse_fixed_point(price) / self.tick_size
+    }
+
+    fn ticks_to_price(&self, ticks: i64) -> f64 {
+        fixed_point_to_f64(ticks * self.tick_size)
+    }
+
+    fn qty_to_lots(&self, qty: &str) -> u64 {
+        (parse_fixed_point(qty) / self.lot_size) as u64
+    }
+
+    fn lots_to_qty(&self, lots: u64) -> f64 {
+        fixed_point_to_f64(lots as i64 * self.lot_size)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InstrumentState {
     Normal,        // Normal processing of updates
     Recovering,    // Currently fetching and applying a snapshot
@@ -15,37 +76,82 @@ pub enum InstrumentState {
     JustStarted,   // Initial state where recovery is always needed
 }
 
+// (price, qty) pairs for one side of the book, best first
+type BookLevels = Vec<(f64, f64)>;
+
+// A lightweight top-N view of both sides of the book, published alongside deltas so
+// subscribers can reconcile without maintaining their own full book.
+#[derive(Debug, Clone)]
+pub struct TopOfBook {
+    // (price, qty) pairs, best first
+    pub bids: Vec<(f64, f64)>,
+    pub asks: Vec<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+// One price level that changed while applying an update, for publishing granular deltas
+// (`MarketEvent::LevelUpdate`) alongside the coarser `TopOfBook` view.
+#[derive(Debug, Clone)]
+pub struct LevelChange {
+    pub side: Side,
+    pub price: f64,
+    pub new_qty: f64,
+    pub removed: bool,
+}
+
 // as we don't have level numbers in incremental updates, BTreeMap can be used for inserting
 // updates, that are ordered by price
 // for simplicity in this task all fields are public
+#[derive(Clone)]
 pub struct OrderBook {
-    // price + qty
-    bids: BTreeMap<OrderedFloat<f64>, f64>,
-    asks: BTreeMap<OrderedFloat<f64>, f64>,
+    filters: SymbolFilters,
+    // ticks + lots, per `filters` - gives every level a precise, hashable identity instead
+    // of keying on a parsed float that two distinct decimal strings could collide on (or
+    // that a removal's re-parse could narrowly miss)
+    bids: BTreeMap<i64, u64>,
+    asks: BTreeMap<i64, u64>,
     // update ID can be interpreted as a sequence number
     pub last_applied_id: u64,
 }
 
 impl OrderBook {
-    pub fn new() -> Self {
+    pub fn new(filters: SymbolFilters) -> Self {
         Self {
+            filters,
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
             last_applied_id: 0,
         }
     }
 
-    pub async fn apply_snapshot_locked(
-        order_book: &Arc<RwLock<Self>>,
-        snapshot: &Snapshot,
-        state: Arc<RwLock<InstrumentState>>)
-    {
-        let mut book = order_book.write().await;
-        book.apply_snapshot(snapshot);
+    // top `depth` levels of each side, best first, for publishing to subscribers
+    pub fn top_of_book(&self, depth: usize) -> TopOfBook {
+        TopOfBook {
+            bids: self.bids.iter().rev().take(depth)
+                .map(|(ticks, lots)| (self.filters.ticks_to_price(*ticks), self.filters.lots_to_qty(*lots)))
+                .collect(),
+            asks: self.asks.iter().take(depth)
+                .map(|(ticks, lots)| (self.filters.ticks_to_price(*ticks), self.filters.lots_to_qty(*lots)))
+                .collect(),
+        }
+    }
 
-        let mut state_lock = state.write().await;
-        *state_lock = InstrumentState::JustRecovered;
-        debug_print!("Instrument state set to JustRecovered.");
+    // every level of each side, best first - the full-depth counterpart of `top_of_book`,
+    // used for publishing a `BookCheckpoint` that a late subscriber can bootstrap from
+    pub fn full_book(&self) -> (BookLevels, BookLevels) {
+        (
+            self.bids.iter().rev()
+                .map(|(ticks, lots)| (self.filters.ticks_to_price(*ticks), self.filters.lots_to_qty(*lots)))
+                .collect(),
+            self.asks.iter()
+                .map(|(ticks, lots)| (self.filters.ticks_to_price(*ticks), self.filters.lots_to_qty(*lots)))
+                .collect(),
+        )
     }
 
     fn apply_snapshot(
@@ -55,33 +161,26 @@ impl OrderBook {
         self.bids.clear();
         self.asks.clear();
         for bid in &snapshot.bids {
-            let price = OrderedFloat(bid[0].parse::<f64>().unwrap());
-            let qty: f64 = bid[1].parse().unwrap();
-            self.bids.insert(price, qty);
+            let ticks = self.filters.price_to_ticks(&bid[0]);
+            let lots = self.filters.qty_to_lots(&bid[1]);
+            self.bids.insert(ticks, lots);
         }
         for ask in &snapshot.asks {
-            let price = OrderedFloat(ask[0].parse::<f64>().unwrap());
-            let qty: f64 = ask[1].parse().unwrap();
-            self.asks.insert(price, qty);
+            let ticks = self.filters.price_to_ticks(&ask[0]);
+            let lots = self.filters.qty_to_lots(&ask[1]);
+            self.asks.insert(ticks, lots);
         }
 
         debug_print!("Applied snapshot");
-        println!("{}", self);
     }
 
-    pub async fn apply_update_locked(
-        order_book: &Arc<RwLock<Self>>,
-        update: &OrderBookUpdate,
-    ) -> Result<(), String> {
-        let mut book = order_book.write().await;
-        book.apply_update(update)
-    }
-
-    pub fn apply_update(&mut self, update: &OrderBookUpdate) -> Result<(), String> {
+    // Returns `Ok(Some((top_of_book, level_changes)))` when the update advanced the book,
+    // `Ok(None)` when it was a no-op (already applied / superseded by a later snapshot).
+    pub fn apply_update(&mut self, update: &OrderBookUpdate) -> Result<Option<(TopOfBook, Vec<LevelChange>)>, String> {
         // in this case we already either processed these updates or restored a
         // later state from snapshot
         if update.last_trade_id <= self.last_applied_id {
-            return Ok(());
+            return Ok(None);
         }
         if update.first_trade_id > self.last_applied_id + 1 {
             return Err(format!(
@@ -91,32 +190,42 @@ impl OrderBook {
             ));
         }
 
+        let mut level_changes = Vec::with_capacity(update.bids.len() + update.asks.len());
+
         for bid in &update.bids {
-            let price = OrderedFloat(bid[0].parse::<f64>().unwrap());
-            let qty: f64 = bid[1].parse().unwrap();
-            // remove level with zero qty
-            if qty == 0.0 {
-                self.bids.remove(&price);
+            let ticks = self.filters.price_to_ticks(&bid[0]);
+            let lots = self.filters.qty_to_lots(&bid[1]);
+            // remove level with zero qty - an exact integer match, unlike comparing parsed
+            // floats for equality
+            let removed = lots == 0;
+            if removed {
+                self.bids.remove(&ticks);
             } else {
-                self.bids.insert(price, qty);
+                self.bids.insert(ticks, lots);
             }
+            level_changes.push(LevelChange {
+                side: Side::Bid, price: self.filters.ticks_to_price(ticks), new_qty: self.filters.lots_to_qty(lots), removed,
+            });
         }
         for ask in &update.asks {
-            let price = OrderedFloat(ask[0].parse::<f64>().unwrap());
-            let qty: f64 = ask[1].parse().unwrap();
-            if qty == 0.0 {
-                self.asks.remove(&price);
+            let ticks = self.filters.price_to_ticks(&ask[0]);
+            let lots = self.filters.qty_to_lots(&ask[1]);
+            let removed = lots == 0;
+            if removed {
+                self.asks.remove(&ticks);
             } else {
-                self.asks.insert(price, qty);
+                self.asks.insert(ticks, lots);
             }
+            level_changes.push(LevelChange {
+                side: Side::Ask, price: self.filters.ticks_to_price(ticks), new_qty: self.filters.lots_to_qty(lots), removed,
+            });
         }
 
         self.last_applied_id = update.last_trade_id;
 
         debug_print!("Applied update");
-        println!("{}", self);
 
-        Ok(())
+        Ok(Some((self.top_of_book(5), level_changes)))
     }
 }
 
@@ -126,15 +235,137 @@ impl fmt::Display for OrderBook {
         writeln!(f, "{}", "Order Book:".blue().bold())?;
 
         writeln!(f, "{}", "Bids:".blue())?;
-        for (price, qty) in self.bids.iter().rev().take(5) {
+        for (ticks, lots) in self.bids.iter().rev().take(5) {
+            let (price, qty) = (self.filters.ticks_to_price(*ticks), self.filters.lots_to_qty(*lots));
             writeln!(f, "{}", format!("  Price: {}, Qty: {}", price, qty).blue().bold())?;
         }
 
         writeln!(f, "{}", "Asks:".blue().bold())?;
-        for (price, qty) in self.asks.iter().take(5) {
+        for (ticks, lots) in self.asks.iter().take(5) {
+            let (price, qty) = (self.filters.ticks_to_price(*ticks), self.filters.lots_to_qty(*lots));
             writeln!(f, "{}", format!("  Price: {}, Qty: {}", price, qty).blue().bold())?;
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// Everything a reader needs in one atomically-published value: the book itself and the
+// instrument state that governs how the next update gets applied to it. Publishing them
+// together means a reader can never observe a new book paired with a stale state (or vice
+// versa), which was possible when the two lived behind independent locks.
+#[derive(Clone)]
+pub struct OrderBookSnapshot {
+    pub book: OrderBook,
+    pub state: InstrumentState,
+}
+
+impl OrderBookSnapshot {
+    fn new(filters: SymbolFilters) -> Self {
+        Self {
+            book: OrderBook::new(filters),
+            state: InstrumentState::JustStarted,
+        }
+    }
+}
+
+// Lock-free reads, single writer: the current snapshot lives behind an `ArcSwap`, so `load()`
+// is just a cheap `Arc` clone with no blocking. Writers serialize through `write_lock` -
+// clone the current snapshot, mutate the clone, then `store` it back - so concurrent callers
+// of `update()` still observe and publish a consistent book+state pair instead of tearing.
+pub struct StateLock {
+    current: ArcSwap<OrderBookSnapshot>,
+    write_lock: Mutex<()>,
+}
+
+impl StateLock {
+    pub fn new(filters: SymbolFilters) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(OrderBookSnapshot::new(filters)),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    // Cheap, non-blocking `Arc` clone of the current snapshot.
+    pub fn load(&self) -> Arc<OrderBookSnapshot> {
+        self.current.load_full()
+    }
+
+    pub fn state(&self) -> InstrumentState {
+        self.load().state
+    }
+
+    pub fn top_of_book(&self, depth: usize) -> TopOfBook {
+        self.load().book.top_of_book(depth)
+    }
+
+    pub fn last_applied_id(&self) -> u64 {
+        self.load().book.last_applied_id
+    }
+
+    // Full-depth (last_applied_id, bids, asks) view for publishing a `BookCheckpoint`
+    // that a late subscriber can bootstrap from before applying subsequent deltas.
+    pub fn checkpoint(&self) -> (u64, BookLevels, BookLevels) {
+        let snapshot = self.load();
+        let (bids, asks) = snapshot.book.full_book();
+        (snapshot.book.last_applied_id, bids, asks)
+    }
+
+    // Runs `mutate` against a clone of the current snapshot under the single-writer lock,
+    // then publishes the result atomically. Whatever `mutate` returns is propagated back to
+    // the caller, e.g. the `Result<Option<(TopOfBook, Vec<LevelChange>)>, String>` of
+    // `OrderBook::apply_update`.
+    pub fn update<T>(&self, mutate: impl FnOnce(&mut OrderBookSnapshot) -> T) -> T {
+        let _guard = self.write_lock.lock();
+        let mut next = (*self.load()).clone();
+        let result = mutate(&mut next);
+        self.current.store(Arc::new(next));
+        result
+    }
+
+    pub fn set_state(&self, state: InstrumentState) {
+        self.update(|snapshot| snapshot.state = state);
+    }
+
+    // Applies a freshly-fetched snapshot and transitions straight to `JustRecovered`,
+    // publishing both atomically.
+    pub fn apply_snapshot(&self, snapshot: &Snapshot) -> TopOfBook {
+        self.update(|current| {
+            current.book.apply_snapshot(snapshot);
+            current.state = InstrumentState::JustRecovered;
+            current.book.top_of_book(5)
+        })
+    }
+
+    // Applies an incremental update to the current book, leaving `state` untouched.
+    pub fn apply_update(&self, update: &OrderBookUpdate) -> Result<Option<(TopOfBook, Vec<LevelChange>)>, String> {
+        self.update(|current| current.book.apply_update(update))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_fixed_point_strings_of_varying_precision_to_the_same_scale() {
+        assert_eq!(parse_fixed_point("1"), 100_000_000);
+        assert_eq!(parse_fixed_point("1.5"), 150_000_000);
+        assert_eq!(parse_fixed_point("0.00000001"), 1);
+        // fewer fractional digits than FIXED_POINT_SCALE are padded, not left misaligned
+        assert_eq!(parse_fixed_point("63350.12"), parse_fixed_point("63350.12000000"));
+    }
+
+    #[test]
+    fn tick_and_lot_roundtrip_through_the_same_symbol_filters() {
+        let filters = SymbolFilters::new("0.01", "0.00001");
+
+        // two decimal strings that round to the same tick/lot must land on the same key -
+        // this is the whole reason ticks/lots replaced OrderedFloat<f64> keys
+        assert_eq!(filters.price_to_ticks("63350.12"), filters.price_to_ticks("63350.1200"));
+        assert_eq!(filters.qty_to_lots("1.23000"), filters.qty_to_lots("1.23"));
+
+        assert_eq!(filters.ticks_to_price(filters.price_to_ticks("63350.12")), 63350.12);
+        assert_eq!(filters.lots_to_qty(filters.qty_to_lots("1.23")), 1.23);
+    }
+}
@@ -1,11 +1,13 @@
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use tokio::time::Instant;
-use crate::messages::{Snapshot};
-use crate::order_book::{OrderBook, InstrumentState};
+use crate::events::{self, MarketEvent};
+use crate::messages::{ExchangeInfo, Snapshot};
+use crate::order_book::{InstrumentState, StateLock, SymbolFilters};
 use crate::event_buffer::EventBuffer;
 use crate::debug_print;
+use crate::shutdown::ShutdownReceiver;
 
 // TimeoutState is used for checking for inactivity in publishing updates. If updates
 // were not published for 5 secs, recovery from snapshot channel will be triggered
@@ -35,40 +37,66 @@ impl TimeoutState {
 }
 
 pub async fn monitor_and_recover(
-    event_buffer: Arc<RwLock<EventBuffer>>,
-    order_book: Arc<RwLock<OrderBook>>,
-    state: Arc<RwLock<InstrumentState>>,
+    symbol: String,
+    event_buffer: Arc<EventBuffer>,
+    state_lock: Arc<StateLock>,
     snapshot_url: String,
     timeout_state: Arc<TimeoutState>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    mut shutdown: ShutdownReceiver,
 ) {
     let trigger_recovery = || async {
         println!("Triggering recovery...");
         recover_order_book(
+            symbol.clone(),
             snapshot_url.clone(),
-            Arc::clone(&order_book),
+            Arc::clone(&state_lock),
             Arc::clone(&event_buffer),
-            Arc::clone(&state),
             timeout_state.clone(),
+            event_sender.clone(),
         )
             .await;
     };
 
-    // Perform initial recovery if the instrument is in the `JustStarted` state
-    if *state.read().await == InstrumentState::JustStarted {
-        debug_print!("Instrument is in JustStarted state, recovery required.");
-        trigger_recovery().await;
+    // Perform initial recovery if the instrument is in the `JustStarted` state, unless the
+    // durable event queue already covers `last_applied_id + 1`, in which case the buffered
+    // updates are applied directly and a fresh snapshot fetch is skipped. `last_applied_id`
+    // is not persisted across restarts, so on a fresh start it is 0 and the snapshot is only
+    // skipped when the earliest queued update starts at id 1 or below.
+    if state_lock.state() == InstrumentState::JustStarted {
+        let lowest_pending = event_buffer.lowest_pending_first_trade_id(&symbol).await.unwrap_or(None);
+        match lowest_pending {
+            Some(first_trade_id) if first_trade_id <= state_lock.last_applied_id() + 1 => {
+                debug_print!(
+                    "Durable queue already covers last_applied_id + 1 (first_trade_id = {}), skipping snapshot recovery.",
+                    first_trade_id
+                );
+                state_lock.set_state(InstrumentState::JustRecovered);
+                event_buffer.process_buffered_updates(&symbol, event_sender.clone()).await;
+            }
+            _ => {
+                debug_print!("Instrument is in JustStarted state, recovery required.");
+                trigger_recovery().await;
+            }
+        }
     }
 
     loop {
-        // Wait for the timeout duration
-        tokio::time::sleep(timeout_state.timeout_duration).await;
-
-        // Check inactivity and trigger recovery if needed
-        if timeout_state.is_timed_out().await {
-            debug_print!("Inactivity timeout reached.");
-            trigger_recovery().await;
-        } else {
-            debug_print!("No inactivity detected. Continuing monitoring...");
+        tokio::select! {
+            _ = shutdown.recv_shutdown() => {
+                debug_print!("Monitor: shutdown requested, stopping.");
+                return;
+            }
+            // Wait for the timeout duration
+            _ = tokio::time::sleep(timeout_state.timeout_duration) => {
+                // Check inactivity and trigger recovery if needed
+                if timeout_state.is_timed_out().await {
+                    debug_print!("Inactivity timeout reached.");
+                    trigger_recovery().await;
+                } else {
+                    debug_print!("No inactivity detected. Continuing monitoring...");
+                }
+            }
         }
     }
 }
@@ -76,54 +104,59 @@ pub async fn monitor_and_recover(
 // here we process snapshot update, apply it to the book and then apply buffered updates
 // that we received during recovery process
 pub(crate) async fn recover_order_book(
+    symbol: String,
     snapshot_url: String,
-    order_book: Arc<RwLock<OrderBook>>,
-    event_buffer: Arc<RwLock<EventBuffer>>,
-    state: Arc<RwLock<InstrumentState>>,
+    state_lock: Arc<StateLock>,
+    event_buffer: Arc<EventBuffer>,
     timeout_state: Arc<TimeoutState>,
+    event_sender: broadcast::Sender<MarketEvent>,
 ) {
-    {
-        let mut state_lock = state.write().await;
-
-        if *state_lock == InstrumentState::JustStarted {
-            debug_print!("Transitioning from JustStarted to Recovering.");
-        } else {
-            debug_print!("Transitioning from Normal to Recovering.");
-        }
-
-        *state_lock = InstrumentState::Recovering;
+    if state_lock.state() == InstrumentState::JustStarted {
+        debug_print!("Transitioning from JustStarted to Recovering.");
+    } else {
+        debug_print!("Transitioning from Normal to Recovering.");
     }
+    state_lock.set_state(InstrumentState::Recovering);
 
     debug_print!("Starting recovery...");
 
     match fetch_snapshot(&snapshot_url).await {
         Ok(snapshot) => {
             debug_print!("Snapshot fetched successfully. Applying snapshot...");
-            // Apply snapshot to the order book
-            OrderBook::apply_snapshot_locked(&order_book, &snapshot, Arc::clone(&state)).await;
+            // Apply snapshot to the order book, transitioning to JustRecovered atomically
+            let top_of_book = state_lock.apply_snapshot(&snapshot);
+            debug_print!("Instrument state set to JustRecovered.");
+            events::publish(&event_sender, MarketEvent::BookSnapshotApplied {
+                symbol: symbol.clone(),
+                last_applied_id: snapshot.last_update_id,
+                top_of_book,
+            });
+
+            // also publish a full-depth checkpoint, so a late subscriber can bootstrap its
+            // own copy of the book from this point and apply subsequent deltas against it
+            let (checkpoint_id, bids, asks) = state_lock.checkpoint();
+            events::publish(&event_sender, MarketEvent::BookCheckpoint {
+                symbol: symbol.clone(),
+                last_applied_id: checkpoint_id,
+                bids,
+                asks,
+            });
 
             timeout_state.reset().await;
 
             debug_print!("Snapshot applied. Processing buffered updates...");
 
             // Process buffered updates immediately after recovery
-            {
-                let mut buffer = event_buffer.write().await;
-                buffer.process_buffered_updates(Arc::clone(&order_book), Arc::clone(&state), Arc::clone(&timeout_state)).await;
-            }
+            event_buffer.process_buffered_updates(&symbol, event_sender.clone()).await;
 
-            {
-                let mut state_lock = state.write().await;
-                *state_lock = InstrumentState::JustRecovered;
-                debug_print!("Instrument state set to JustRecovered.");
-            }
+            state_lock.set_state(InstrumentState::JustRecovered);
+            debug_print!("Instrument state set to JustRecovered.");
 
             debug_print!("Recovery complete.");
         }
         Err(err) => {
             eprintln!("Failed to recover order book: {}", err);
-            let mut state_lock = state.write().await;
-            *state_lock = InstrumentState::Normal; // Reset to normal even if recovery fails
+            state_lock.set_state(InstrumentState::Normal); // Reset to normal even if recovery fails
         }
     }
 }
@@ -158,3 +191,33 @@ pub(crate) async fn fetch_snapshot(
         }
     }
 }
+
+// fetch the symbol's tick/lot size from exchangeInfo once at startup, so the order book can
+// be keyed on fixed-point ticks/lots instead of re-parsing and comparing floats on every update
+pub(crate) async fn fetch_symbol_filters(symbol: &str) -> Result<SymbolFilters, String> {
+    let url = format!("https://api.binance.com/api/v3/exchangeInfo?symbol={}", symbol);
+    debug_print!("Fetching exchange info from: {}", url);
+
+    let response = reqwest::get(&url).await
+        .map_err(|err| format!("Failed to fetch exchange info: {:?}", err))?;
+    let raw_json = response.text().await
+        .map_err(|err| format!("Failed to read exchange info response: {:?}", err))?;
+    let info: ExchangeInfo = serde_json::from_str(&raw_json)
+        .map_err(|err| format!("Failed to parse exchange info JSON: {:?}", err))?;
+
+    let symbol_info = info.symbols.into_iter().next()
+        .ok_or_else(|| format!("Symbol {} not found in exchange info", symbol))?;
+
+    let tick_size = symbol_info.filters.iter()
+        .find(|filter| filter.get("filterType").and_then(|v| v.as_str()) == Some("PRICE_FILTER"))
+        .and_then(|filter| filter.get("tickSize")).and_then(|v| v.as_str())
+        .ok_or_else(|| "Missing PRICE_FILTER.tickSize in exchange info".to_string())?;
+    let lot_size = symbol_info.filters.iter()
+        .find(|filter| filter.get("filterType").and_then(|v| v.as_str()) == Some("LOT_SIZE"))
+        .and_then(|filter| filter.get("stepSize")).and_then(|v| v.as_str())
+        .ok_or_else(|| "Missing LOT_SIZE.stepSize in exchange info".to_string())?;
+
+    debug_print!("Fetched filters for {}: tickSize={}, stepSize={}", symbol, tick_size, lot_size);
+
+    Ok(SymbolFilters::new(tick_size, lot_size))
+}
@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use colored::Colorize;
+use ordered_float::OrderedFloat;
+use rand::Rng;
+use tokio::sync::broadcast;
+use tokio::time::{Duration, Instant};
+
+use crate::debug_print;
+use crate::event_buffer::EventBuffer;
+use crate::events::MarketEvent;
+use crate::level_parse::parse_level;
+use crate::messages::Snapshot;
+use crate::order_book::StateLock;
+use crate::recovery::{fetch_snapshot, recover_order_book, TimeoutState};
+use crate::shutdown::ShutdownReceiver;
+
+// Tunables for the periodic full-book scrub.
+#[derive(Debug, Clone)]
+pub struct ScrubConfig {
+    pub interval: Duration,
+    // +/- this fraction of `interval` is added as jitter, so many scrubbers (one per tracked
+    // symbol) don't all hit the REST endpoint in lockstep
+    pub jitter_ratio: f64,
+    // "tranquilizer": the comparison's own duration is multiplied by this factor and slept
+    // afterwards, so a slow comparison (large book, loaded runtime) doesn't compound by
+    // immediately kicking off the next cycle
+    pub tranquilizer_factor: f64,
+    pub qty_tolerance: f64,
+}
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            jitter_ratio: 0.2,
+            tranquilizer_factor: 1.0,
+            qty_tolerance: 1e-6,
+        }
+    }
+}
+
+// Periodically fetches a fresh depth snapshot over REST and compares it level-by-level
+// against the live `OrderBook`. Unlike the `first_trade_id`/`last_trade_id` continuity checks
+// in `apply_update`, this catches silent corruption that doesn't show up as a sequence
+// gap - e.g. a level that quietly dropped to zero without ever going through a zero-qty
+// update, or a price level the built book still carries after it should have been removed.
+pub async fn run_book_scrubber(
+    symbol: String,
+    state_lock: Arc<StateLock>,
+    event_buffer: Arc<EventBuffer>,
+    snapshot_url: String,
+    timeout_state: Arc<TimeoutState>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    shutdown: ShutdownReceiver,
+) {
+    run_book_scrubber_with_config(
+        symbol, state_lock, event_buffer, snapshot_url, timeout_state, event_sender,
+        shutdown, ScrubConfig::default(),
+    ).await;
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn run_book_scrubber_with_config(
+    symbol: String,
+    state_lock: Arc<StateLock>,
+    event_buffer: Arc<EventBuffer>,
+    snapshot_url: String,
+    timeout_state: Arc<TimeoutState>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    mut shutdown: ShutdownReceiver,
+    config: ScrubConfig,
+) {
+    loop {
+        tokio::select! {
+            _ = shutdown.recv_shutdown() => {
+                debug_print!("Scrubber [{}]: shutdown requested, stopping.", symbol);
+                return;
+            }
+            _ = tokio::time::sleep(jittered_interval(&config)) => {}
+        }
+
+        let scrub_started = Instant::now();
+
+        match fetch_snapshot(&snapshot_url).await {
+            Ok(snapshot) => {
+                if let Some(mismatch) = find_divergence(&state_lock, &snapshot, config.qty_tolerance) {
+                    eprintln!("{}", format!(
+                        "Scrubber [{}]: built book diverged from fresh snapshot ({}), triggering recovery",
+                        symbol, mismatch
+                    ).red().bold());
+
+                    recover_order_book(
+                        symbol.clone(), snapshot_url.clone(), Arc::clone(&state_lock), Arc::clone(&event_buffer),
+                        Arc::clone(&timeout_state), event_sender.clone(),
+                    ).await;
+                } else {
+                    debug_print!("Scrubber [{}]: book matches fresh snapshot within tolerance.", symbol);
+                }
+            }
+            Err(err) => {
+                eprintln!("{}", format!("Scrubber [{}]: failed to fetch snapshot for scrub: {}", symbol, err).red().bold());
+            }
+        }
+
+        let tranquilizer = scrub_started.elapsed().mul_f64(config.tranquilizer_factor);
+        if !tranquilizer.is_zero() {
+            debug_print!("Scrubber [{}]: tranquilizing for {:?} before the next cycle.", symbol, tranquilizer);
+            tokio::time::sleep(tranquilizer).await;
+        }
+    }
+}
+
+fn jittered_interval(config: &ScrubConfig) -> Duration {
+    let jitter = rand::thread_rng().gen_range((1.0 - config.jitter_ratio)..=(1.0 + config.jitter_ratio));
+    config.interval.mul_f64(jitter)
+}
+
+// Returns a human-readable description of the first level that diverges beyond tolerance
+// (on either side, or in a level's mere presence), if any.
+fn find_divergence(state_lock: &StateLock, snapshot: &Snapshot, qty_tolerance: f64) -> Option<String> {
+    let (_, built_bids, built_asks) = state_lock.checkpoint();
+
+    if let Some(mismatch) = diff_side("bid", &built_bids, &snapshot.bids, qty_tolerance) {
+        return Some(mismatch);
+    }
+    if let Some(mismatch) = diff_side("ask", &built_asks, &snapshot.asks, qty_tolerance) {
+        return Some(mismatch);
+    }
+
+    None
+}
+
+fn diff_side(side: &str, built: &[(f64, f64)], fresh: &[[String; 2]], qty_tolerance: f64) -> Option<String> {
+    let built_levels: BTreeMap<OrderedFloat<f64>, f64> = built.iter().map(|(price, qty)| (OrderedFloat(*price), *qty)).collect();
+
+    let mut fresh_levels: BTreeMap<OrderedFloat<f64>, f64> = BTreeMap::new();
+    for level in fresh {
+        let (price, qty) = match parse_level(&level[0], &level[1]) {
+            Ok(parsed) => parsed,
+            // an unparseable level can't be compared, but it must not be waved through as a
+            // match either - treat it as a divergence so it forces the same recovery a real
+            // mismatch would
+            Err(err) => return Some(format!(
+                "{} level [{}, {}] failed to parse ({}), treating as divergence", side, level[0], level[1], err
+            )),
+        };
+        fresh_levels.insert(OrderedFloat(price), qty);
+    }
+
+    for (price, fresh_qty) in &fresh_levels {
+        match built_levels.get(price) {
+            Some(built_qty) if (built_qty - fresh_qty).abs() <= qty_tolerance => {}
+            Some(built_qty) => {
+                return Some(format!(
+                    "{} level {} qty mismatch: built={}, fresh={}", side, price.into_inner(), built_qty, fresh_qty
+                ));
+            }
+            None => {
+                return Some(format!(
+                    "{} level {} missing from built book (fresh snapshot shows qty={})", side, price.into_inner(), fresh_qty
+                ));
+            }
+        }
+    }
+
+    for price in built_levels.keys() {
+        if !fresh_levels.contains_key(price) {
+            return Some(format!(
+                "{} level {} present in built book but absent from fresh snapshot", side, price.into_inner()
+            ));
+        }
+    }
+
+    None
+}
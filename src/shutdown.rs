@@ -0,0 +1,45 @@
+use tokio::sync::watch;
+
+// Shared shutdown signal, fanned out from the Ctrl-C handler in `main` to every feed loop,
+// the monitor/recovery loop, and the reconnection supervisors. A `watch` channel is used
+// rather than `broadcast` because every receiver only ever cares about the latest value
+// ("are we shutting down yet?"), not about replaying past sends, and new clones taken after
+// shutdown has already fired must observe it immediately.
+#[derive(Clone)]
+pub struct ShutdownSender(watch::Sender<bool>);
+
+impl ShutdownSender {
+    pub fn shutdown(&self) {
+        let _ = self.0.send(true);
+    }
+}
+
+#[derive(Clone)]
+pub struct ShutdownReceiver(watch::Receiver<bool>);
+
+impl ShutdownReceiver {
+    pub fn is_shutdown(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    // Resolves once shutdown has been signalled. Safe to use as a `tokio::select!` branch
+    // alongside `read.next()` / `sleep()`: it resolves immediately if shutdown already fired
+    // instead of only reacting to a fresh change.
+    pub async fn recv_shutdown(&mut self) {
+        if self.is_shutdown() {
+            return;
+        }
+        while self.0.changed().await.is_ok() {
+            if self.is_shutdown() {
+                return;
+            }
+        }
+        // the sender was dropped without ever signalling shutdown: never resolve
+        std::future::pending::<()>().await
+    }
+}
+
+pub fn channel() -> (ShutdownSender, ShutdownReceiver) {
+    let (tx, rx) = watch::channel(false);
+    (ShutdownSender(tx), ShutdownReceiver(rx))
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use colored::Colorize;
+use tokio::sync::{broadcast, RwLock};
+use tokio::time::Duration;
+
+use crate::debug_print;
+use crate::event_buffer::EventBuffer;
+use crate::events::MarketEvent;
+use crate::level_parse::parse_level;
+use crate::messages::BestDeal;
+use crate::order_book::{StateLock, TopOfBook};
+use crate::recovery::{recover_order_book, TimeoutState};
+use crate::shutdown::ShutdownReceiver;
+
+// Tunables for the periodic book-vs-depth5 cross-check
+#[derive(Debug, Clone)]
+pub struct ValidatorConfig {
+    pub check_interval: Duration,
+    // relative tolerance (e.g. 0.0001 = 1 bps) to absorb timing skew between the built book
+    // and the latest depth5 snapshot; applied to price, and to qty as a relative bound
+    // alongside the absolute `qty_tolerance` (whichever is larger)
+    pub price_tolerance: f64,
+    pub qty_tolerance: f64,
+}
+
+impl Default for ValidatorConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_secs(10),
+            price_tolerance: 0.0001,
+            qty_tolerance: 1e-6,
+        }
+    }
+}
+
+// Periodically compares the locally built `OrderBook` against the latest `<symbol>@depth5`
+// snapshot. The two are maintained completely independently, so a dropped or misapplied
+// delta can otherwise leave the built book silently wrong until the unrelated inactivity
+// timer in `monitor_and_recover` happens to fire. On divergence beyond tolerance this
+// triggers recovery immediately instead of waiting for that.
+pub async fn run_book_validator(
+    symbol: String,
+    state_lock: Arc<StateLock>,
+    latest_best_deals: Arc<RwLock<HashMap<String, BestDeal>>>,
+    event_buffer: Arc<EventBuffer>,
+    snapshot_url: String,
+    timeout_state: Arc<TimeoutState>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    shutdown: ShutdownReceiver,
+) {
+    run_book_validator_with_config(
+        symbol, state_lock, latest_best_deals, event_buffer, snapshot_url, timeout_state, event_sender,
+        shutdown, ValidatorConfig::default(),
+    ).await;
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn run_book_validator_with_config(
+    symbol: String,
+    state_lock: Arc<StateLock>,
+    latest_best_deals: Arc<RwLock<HashMap<String, BestDeal>>>,
+    event_buffer: Arc<EventBuffer>,
+    snapshot_url: String,
+    timeout_state: Arc<TimeoutState>,
+    event_sender: broadcast::Sender<MarketEvent>,
+    mut shutdown: ShutdownReceiver,
+    config: ValidatorConfig,
+) {
+    loop {
+        tokio::select! {
+            _ = shutdown.recv_shutdown() => {
+                debug_print!("Validator [{}]: shutdown requested, stopping.", symbol);
+                return;
+            }
+            _ = tokio::time::sleep(config.check_interval) => {}
+        }
+
+        let best_deal = latest_best_deals.read().await.get(&symbol).cloned();
+        let Some(best_deal) = best_deal else {
+            debug_print!("Validator [{}]: no depth5 snapshot received yet, skipping check.", symbol);
+            continue;
+        };
+
+        let top_of_book = state_lock.top_of_book(1);
+
+        if let Some(mismatch) = find_divergence(&top_of_book, &best_deal, config.price_tolerance, config.qty_tolerance) {
+            eprintln!("{}", format!(
+                "Validator [{}]: built book diverged from depth5 snapshot ({}), triggering recovery",
+                symbol, mismatch
+            ).red().bold());
+
+            recover_order_book(
+                symbol.clone(), snapshot_url.clone(), Arc::clone(&state_lock), Arc::clone(&event_buffer),
+                Arc::clone(&timeout_state), event_sender.clone(),
+            ).await;
+        } else {
+            debug_print!("Validator [{}]: book matches depth5 snapshot within tolerance.", symbol);
+        }
+    }
+}
+
+// Returns a human-readable description of the first side that diverges beyond tolerance, if
+// any.
+fn find_divergence(top_of_book: &TopOfBook, best_deal: &BestDeal, price_tolerance: f64, qty_tolerance: f64) -> Option<String> {
+    if let Some(mismatch) = compare_level("bid", top_of_book.bids.first(), best_deal.bids.first(), price_tolerance, qty_tolerance) {
+        return Some(mismatch);
+    }
+    if let Some(mismatch) = compare_level("ask", top_of_book.asks.first(), best_deal.asks.first(), price_tolerance, qty_tolerance) {
+        return Some(mismatch);
+    }
+    None
+}
+
+fn compare_level(
+    side: &str,
+    built: Option<&(f64, f64)>,
+    reference: Option<&[String; 2]>,
+    price_tolerance: f64,
+    qty_tolerance: f64,
+) -> Option<String> {
+    match (built, reference) {
+        (Some(&(built_price, built_qty)), Some([ref_price, ref_qty])) => {
+            let (ref_price, ref_qty) = match parse_level(ref_price, ref_qty) {
+                Ok(parsed) => parsed,
+                // an unparseable reference level can't be compared, but it must not be waved
+                // through as a match either - treat it as a divergence so it forces the same
+                // recovery a real mismatch would
+                Err(err) => return Some(format!(
+                    "best {} level [{}, {}] failed to parse ({}), treating as divergence", side, ref_price, ref_qty, err
+                )),
+            };
+
+            let price_diverges = (built_price - ref_price).abs() > ref_price * price_tolerance;
+            let qty_diverges = (built_qty - ref_qty).abs() > qty_tolerance.max(ref_qty * price_tolerance);
+
+            if price_diverges || qty_diverges {
+                Some(format!(
+                    "best {} price/qty mismatch: built=({}, {}), depth5=({}, {})",
+                    side, built_price, built_qty, ref_price, ref_qty
+                ))
+            } else {
+                None
+            }
+        }
+        (None, None) => None,
+        _ => Some(format!("best {} presence mismatch between built book and depth5 snapshot", side)),
+    }
+}